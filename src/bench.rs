@@ -0,0 +1,174 @@
+// Synthetic workloads for `linch bench`, generated in a temporary directory
+// so timings don't depend on what the machine has installed.
+
+use std::fs::{create_dir_all, remove_dir_all, set_permissions, write, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
+
+use crate::protocol::{self, BenchReport, BenchResult};
+use crate::{cache_sort, dmenu_items, CacheEntry};
+use linch::discovery::{applications_in, binaries_in, Item};
+use linch::matching::{self, search_matches};
+
+/// Runs per benchmark, keeping the fastest
+const RUNS: usize = 3;
+
+const WORDS: [&str; 16] = [
+    "audio", "browser", "calc", "disk", "editor", "files", "game", "image", "mail", "music", "office", "paint",
+    "settings", "terminal", "video", "web",
+];
+
+/// Deterministic so separate runs share a workload
+fn name(n: usize) -> String {
+    let x = (n as u64).wrapping_mul(0x9E3779B97F4A7C15) >> 32;
+    format!("{} {} {}", WORDS[(x % 16) as usize], WORDS[(x / 16 % 16) as usize], n)
+}
+
+fn best<T>(mut f: impl FnMut() -> T) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let now = Instant::now();
+            std::hint::black_box(f());
+            now.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn result(name: impl Into<String>, size: usize, time: Duration) -> BenchResult {
+    BenchResult {
+        name: name.into(),
+        size,
+        micros: time.as_micros() as u64,
+    }
+}
+
+pub fn run(entries: usize, binaries: usize, lines: usize, json: bool) {
+    let root = std::env::temp_dir().join(format!("linch-bench-{}", std::process::id()));
+    let data = root.join("data");
+    let apps = data.join("applications");
+    let bins = root.join("bin");
+    create_dir_all(&apps)
+        .and_then(|_| create_dir_all(&bins))
+        .expect("Could not create bench directory");
+
+    for n in 0..entries {
+        let actions = if n % 10 == 0 {
+            "Actions=new;\n\n[Desktop Action new]\nName=New\nExec=bench --new\n"
+        } else {
+            ""
+        };
+        write(
+            apps.join(format!("bench-{}.desktop", n)),
+            format!(
+                "[Desktop Entry]\nType=Application\nName={}\nComment=Synthetic entry {}\nExec=bench-{} %U\nIcon=bench-{}\nKeywords={};\nCategories=Utility;\n{}",
+                name(n),
+                n,
+                n,
+                n,
+                name(n + 1),
+                actions
+            ),
+        )
+        .expect("Could not write bench entry");
+    }
+    for n in 0..binaries {
+        let path = bins.join(format!("bench-{}", n));
+        write(&path, "")
+            .and_then(|_| set_permissions(&path, Permissions::from_mode(0o755)))
+            .expect("Could not write bench binary");
+    }
+    let items: Vec<Item> = (0..lines)
+        .map(|n| Item {
+            name: name(n).into(),
+            ..Default::default()
+        })
+        .collect();
+    let input: String = (0..lines).map(|n| name(n) + "\n").collect();
+    let input_file = root.join("input");
+    write(&input_file, &input).expect("Could not write bench input");
+    let cache: Vec<CacheEntry> = (0..lines)
+        .step_by(10)
+        .map(|n| CacheEntry {
+            count: n % 7 + 1,
+            pinned: n % 1000 == 0,
+            last: None,
+            name: name(n),
+        })
+        .collect();
+
+    let mut results = vec![
+        result(
+            "get_applications",
+            entries,
+            best(|| applications_in(vec![data.clone()], false, &[], None, false)),
+        ),
+        result(
+            "get_binaries",
+            binaries,
+            best(|| binaries_in(std::iter::once(bins.clone()))),
+        ),
+        // the line by line stdin path from before --input-raw
+        result(
+            "dmenu_lines",
+            lines,
+            best(|| {
+                std::io::BufRead::lines(input.as_bytes())
+                    .map(|l| Item {
+                        name: l.unwrap_or_default().into(),
+                        ..Default::default()
+                    })
+                    .collect::<Vec<_>>()
+            }),
+        ),
+        result(
+            "dmenu_input_raw",
+            lines,
+            best(|| dmenu_items(&std::fs::read(&input_file).unwrap(), false, false, false, None)),
+        ),
+        result(
+            "cache_apply",
+            lines,
+            best(|| {
+                let mut items = items.clone();
+                cache_sort(cache.clone(), &mut items, true, 30.0);
+                items
+            }),
+        ),
+    ];
+    for len in 1..=5 {
+        let query = &"music"[..len];
+        // compiled the same way as the search field
+        let re = matching::compile(query, false).unwrap();
+        results.push(result(
+            format!("match_regex_{}", len),
+            lines,
+            best(|| {
+                items
+                    .iter()
+                    .filter(|i| search_matches(query, Some(&re), &i.name, false))
+                    .count()
+            }),
+        ));
+        results.push(result(
+            format!("match_literal_{}", len),
+            lines,
+            best(|| {
+                items
+                    .iter()
+                    .filter(|i| search_matches(query, None, &i.name, false))
+                    .count()
+            }),
+        ));
+    }
+    remove_dir_all(&root).ok();
+
+    if json {
+        println!("{}", protocol::to_json(BenchReport { results }));
+    } else {
+        println!("{:<20}{:>8}{:>12}", "benchmark", "size", "best");
+        for r in results {
+            println!("{:<20}{:>8}{:>10.2}ms", r.name, r.size, r.micros as f64 / 1000.0);
+        }
+    }
+}
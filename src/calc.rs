@@ -0,0 +1,256 @@
+// Arithmetic for `linch calc`, parsed by recursive descent.
+// Supports + - * / % ^, parentheses, pi and e, and a few functions like sqrt(2).
+
+/// Deepest nesting of parentheses, signs, powers, and functions,
+/// as pasted input could otherwise recurse until the stack overflows
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    rest: &'a str,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// Run one nested rule, failing past MAX_DEPTH
+    fn nested(&mut self, rule: fn(&mut Self) -> Option<f64>) -> Option<f64> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let value = rule(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.rest = self.rest.trim_start();
+        self.rest.chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.rest = &self.rest[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    /// product (('+' | '-') product)*
+    fn sum(&mut self) -> Option<f64> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?
+            } else if self.eat('-') {
+                value -= self.product()?
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    /// unary (('*' | '/' | '%') unary)*, with `2pi` and `3(1+1)` multiplying implicitly
+    fn product(&mut self) -> Option<f64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') || self.eat('×') {
+                value *= self.unary()?
+            } else if self.eat('/') || self.eat('÷') {
+                value /= self.unary()?
+            } else if self.eat('%') {
+                value %= self.unary()?
+            } else if self.peek().is_some_and(|c| c == '(' || c.is_alphabetic()) {
+                value *= self.unary()?
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    /// Signs bind looser than powers so -2^2 is -4
+    fn unary(&mut self) -> Option<f64> {
+        if self.eat('-') {
+            Some(-self.nested(Self::unary)?)
+        } else if self.eat('+') {
+            self.nested(Self::unary)
+        } else {
+            self.power()
+        }
+    }
+
+    /// Right associative
+    fn power(&mut self) -> Option<f64> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Some(base.powf(self.nested(Self::unary)?))
+        } else {
+            Some(base)
+        }
+    }
+
+    fn atom(&mut self) -> Option<f64> {
+        let c = self.peek()?;
+        if self.eat('(') {
+            let value = self.nested(Self::sum)?;
+            self.eat(')').then_some(value)
+        } else if c.is_ascii_digit() || c == '.' {
+            let bytes = self.rest.as_bytes();
+            let mut end = 0;
+            while end < bytes.len() {
+                end += match bytes[end..] {
+                    [b'0'..=b'9' | b'.' | b'_', ..] => 1,
+                    // only an exponent if digits follow, otherwise `2e` is 2 times e
+                    [b'e' | b'E', b'0'..=b'9', ..] => 2,
+                    [b'e' | b'E', b'-' | b'+', b'0'..=b'9', ..] => 3,
+                    _ => break,
+                }
+            }
+            let (number, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            number.replace('_', "").parse().ok()
+        } else if c.is_alphabetic() {
+            let end = self.rest.find(|c: char| !c.is_alphabetic()).unwrap_or(self.rest.len());
+            let (name, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            let f: fn(f64) -> f64 = match name.to_ascii_lowercase().as_str() {
+                "pi" | "π" => return Some(std::f64::consts::PI),
+                "e" => return Some(std::f64::consts::E),
+                "tau" | "τ" => return Some(std::f64::consts::TAU),
+                "sqrt" => f64::sqrt,
+                "cbrt" => f64::cbrt,
+                "abs" => f64::abs,
+                "floor" => f64::floor,
+                "ceil" => f64::ceil,
+                "round" => f64::round,
+                "ln" => f64::ln,
+                "log" => f64::log10,
+                "exp" => f64::exp,
+                "sin" => f64::sin,
+                "cos" => f64::cos,
+                "tan" => f64::tan,
+                "asin" => f64::asin,
+                "acos" => f64::acos,
+                "atan" => f64::atan,
+                _ => return None,
+            };
+            Some(f(self.nested(Self::power)?))
+        } else {
+            None
+        }
+    }
+}
+
+/// The value of `expr`, or None if it isn't a complete expression
+pub fn eval(expr: &str) -> Option<f64> {
+    let mut parser = Parser { rest: expr, depth: 0 };
+    let value = parser.sum()?;
+    parser.peek().is_none().then_some(value)
+}
+
+/// Whole numbers without a decimal point and the rest to 12 significant digits,
+/// switching to exponents when very large or small
+pub fn format(value: f64) -> String {
+    let magnitude = value.abs();
+    if !value.is_finite() || value == 0.0 {
+        value.to_string()
+    } else if !(1e-6..1e15).contains(&magnitude) {
+        let s = format!("{:.11e}", value);
+        let (mantissa, exponent) = s.split_once('e').unwrap_or((&s, "0"));
+        format!("{}e{}", mantissa.trim_end_matches('0').trim_end_matches('.'), exponent)
+    } else if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        let digits = (11 - magnitude.log10().floor() as i32).max(0) as usize;
+        let s = format!("{:.*}", digits, value);
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(expr: &str, expected: f64) {
+        let value = eval(expr).unwrap_or_else(|| panic!("{} didn't parse", expr));
+        assert!(
+            (value - expected).abs() < 1e-9,
+            "{} = {}, not {}",
+            expr,
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        near("1 + 2 * 3", 7.0);
+        near("10 - 4 - 3", 3.0);
+        near("7 % 4 / 2", 1.5);
+        near("-2^2", -4.0);
+        near("2^-1", 0.5);
+        // right associative
+        near("2^3^2", 512.0);
+        near("--+3", 3.0);
+        near("6 × 2 ÷ 4", 3.0);
+    }
+
+    #[test]
+    fn numbers_and_constants() {
+        near("1_000.5", 1000.5);
+        near(".5", 0.5);
+        near("2e3", 2000.0);
+        near("2E-3", 0.002);
+        // no digits after, so e is the constant
+        near("2e", 2.0 * std::f64::consts::E);
+        near("2pi", std::f64::consts::TAU);
+        near("PI", std::f64::consts::PI);
+    }
+
+    #[test]
+    fn implicit_and_functions() {
+        near("3(1+1)", 6.0);
+        near("(1+1)(2+2)", 8.0);
+        near("sqrt(16)", 4.0);
+        near("sqrt 16", 4.0);
+        // applies to a power, so binds tighter than the product
+        near("sqrt 2^2 * 3", 6.0);
+        near("2sqrt(9)", 6.0);
+        near("abs(-3) + floor(2.7) + ceil(0.2)", 6.0);
+        near("log(1000) + ln(e)", 4.0);
+    }
+
+    #[test]
+    fn rejects() {
+        for expr in ["", "1 +", "1 + 1)", "(1 + 1", "2 3", "foo(1)", "1 $", "sqrt"] {
+            assert_eq!(eval(expr), None, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn deep_nesting() {
+        near(&format!("{}1{}", "(".repeat(100), ")".repeat(100)), 1.0);
+        // past the limit fails instead of overflowing the stack
+        for deep in [
+            "(".repeat(100_000),
+            "-".repeat(100_000) + "1",
+            "sqrt ".repeat(100_000) + "1",
+        ] {
+            assert_eq!(eval(&deep), None);
+        }
+        assert_eq!(eval(&"2^".repeat(100_000)), None);
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format(4.0), "4");
+        assert_eq!(format(-2.5), "-2.5");
+        assert_eq!(format(0.1 + 0.2), "0.3");
+        assert_eq!(format(1.0 / 3.0), "0.333333333333");
+        assert_eq!(format(123456.789), "123456.789");
+        assert_eq!(format(1e20), "1e20");
+        assert_eq!(format(-1.5e-7), "-1.5e-7");
+        assert_eq!(format(0.0), "0");
+        assert_eq!(format(f64::INFINITY), "inf");
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use colcon::{convert_space, convert_space_chunked, Space};
+use eframe::egui::{Color32, ColorImage, Rgba};
+use rayon::prelude::*;
+
+pub fn monochromatize(mut reference: [f32; 3], target: &mut [[f32; 4]], target_space: Space) {
+    // {{{
+    convert_space(Space::LRGB, Space::JZCZHZ, &mut reference);
+
+    let [lmax, cmax, _] = Space::JZCZHZ.srgb_quants()[100];
+    let [lmin, cmin, _] = Space::JZCZHZ.srgb_quants()[0];
+    let ref_delta = colcon::hk_high2023(&[100.0, 100.0, reference[2]]);
+
+    // large enough that a single icon's colors stay on one thread
+    target.par_chunks_mut(4096).for_each(|chunk| {
+        convert_space_chunked(target_space, Space::JZCZHZ, chunk);
+        chunk.iter_mut().for_each(|pixel| {
+            let l = (pixel[0] - lmin) / lmax + lmin / lmax;
+            let c = (pixel[1] - cmin) / cmax + cmin / cmax;
+            let h = pixel[2];
+
+            // set hue
+            pixel[2] = reference[2];
+
+            // adjust chroma up to 50% based on proximity to middle gray
+            pixel[1] = c * 0.5 + (0.5 - (l - 0.5).abs());
+
+            // uses a reverse HK delta to exacurbate dark and light hues against the reference
+            let tar_delta = colcon::hk_high2023(&[100.0, 100.0, h]);
+            pixel[0] = l + (ref_delta - tar_delta) / 100.0 / 2.0;
+
+            pixel[0] = (pixel[0] + lmin / lmax) * lmax;
+            pixel[1] = (pixel[1] + lmin / lmax) * lmax;
+        });
+        convert_space_chunked(Space::JZCZHZ, target_space, chunk);
+    });
+} // }}}
+
+/// Fibonacci hashing for packed pixels, as SipHash costs more than it saves on icons of mostly unique colors
+#[derive(Default)]
+struct PixelHasher(u64);
+
+impl Hasher for PixelHasher {
+    fn write(&mut self, _: &[u8]) {
+        unreachable!("only u32 pixels are hashed")
+    }
+    fn write_u32(&mut self, n: u32) {
+        self.0 = (n as u64).wrapping_mul(0x9E3779B97F4A7C15)
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Recolor a rendered icon towards `accent`
+pub fn monochromatize_image(accent: Color32, image: &mut ColorImage) {
+    let acc = Rgba::from(accent);
+    // flat icon art repeats a handful of colors, so each is converted once.
+    // Transparent pixels stay as they are
+    let key = |pixel: &Color32| u32::from_ne_bytes(pixel.to_array());
+    let mut unique = HashMap::<u32, u32, BuildHasherDefault<PixelHasher>>::default();
+    let mut colors = Vec::<[f32; 4]>::new();
+    for pixel in image.pixels.iter().filter(|p| p.a() != 0) {
+        unique.entry(key(pixel)).or_insert_with(|| {
+            colors.push(Rgba::from(*pixel).to_rgba_unmultiplied());
+            colors.len() as u32 - 1
+        });
+    }
+
+    monochromatize([acc[0], acc[1], acc[2]], &mut colors, Space::LRGB);
+
+    let colors: Vec<Color32> = colors
+        .into_iter()
+        .map(|p| Color32::from(Rgba::from_rgba_unmultiplied(p[0], p[1], p[2], p[3])))
+        .collect();
+    for pixel in image.pixels.iter_mut().filter(|p| p.a() != 0) {
+        *pixel = colors[unique[&key(pixel)] as usize]
+    }
+}
+
+/// Flatten a single color icon to `color`, keeping its alpha
+pub fn tint_image(color: Color32, image: &mut ColorImage) {
+    for pixel in image.pixels.iter_mut() {
+        *pixel = Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), pixel.a())
+    }
+}
+
+/// Derive `[foreground, background, accent, hover, selection]` from a single accent, all in LRGB.
+/// Background is a very dark low chroma accent, foreground a near white tinted towards the accent hue,
+/// and the accent itself is clamped in lightness so it stays readable against both.
+/// Hover is a lighter accent for outlines and text over the background,
+/// and selection a dim accent behind selected input text.
+pub fn palette_from_accent(accent: [f32; 3]) -> [[f32; 3]; 5] {
+    // {{{
+    let [lmax, cmax, _] = Space::JZCZHZ.srgb_quants()[100];
+
+    let mut acc = accent;
+    convert_space(Space::LRGB, Space::JZCZHZ, &mut acc);
+    let [l, c, h] = acc;
+
+    let mut palette = [
+        // foreground
+        [lmax * 0.95, (c * 0.1).min(cmax * 0.05), h],
+        // background
+        [lmax * 0.08, (c * 0.2).min(cmax * 0.1), h],
+        // accent
+        [l.clamp(lmax * 0.5, lmax * 0.85), c, h],
+        // hover
+        [(l + lmax * 0.1).clamp(lmax * 0.6, lmax * 0.9), c, h],
+        // selection
+        [lmax * 0.3, (c * 0.6).min(cmax * 0.5), h],
+    ];
+
+    // drop chroma until in gamut so clipping doesn't throw off the lightness
+    palette.iter_mut().for_each(|p| {
+        let mut lrgb = *p;
+        convert_space(Space::JZCZHZ, Space::LRGB, &mut lrgb);
+        while p[1] > 0.0 && lrgb.iter().any(|c| !(-1e-4..=1.0001).contains(c)) {
+            p[1] = (p[1] - cmax * 0.01).max(0.0);
+            lrgb = *p;
+            convert_space(Space::JZCZHZ, Space::LRGB, &mut lrgb);
+        }
+        *p = lrgb
+    });
+    palette
+} // }}}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// JzCzHz lightness as a fraction of sRGB white, after clipping to what can be shown
+    fn lightness(lrgb: [f32; 3]) -> f32 {
+        let mut shown = lrgb.map(|c| c.clamp(0.0, 1.0));
+        convert_space(Space::LRGB, Space::JZCZHZ, &mut shown);
+        shown[0] / Space::JZCZHZ.srgb_quants()[100][0]
+    }
+
+    #[test]
+    fn accent_palette_bounds() {
+        let mut accents = vec![[0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [0.02, 0.02, 0.02], [0.9, 0.9, 0.9]];
+        for hue in (0..360).step_by(30) {
+            for (l, c) in [(0.3, 0.1), (0.6, 0.15), (0.9, 0.05)] {
+                let mut lrgb = [l * Space::JZCZHZ.srgb_quants()[100][0], c, hue as f32];
+                convert_space(Space::JZCZHZ, Space::LRGB, &mut lrgb);
+                accents.push(lrgb.map(|c| c.clamp(0.0, 1.0)))
+            }
+        }
+        for accent in accents {
+            let [fg, bg, acc, hover, selection] = palette_from_accent(accent).map(lightness);
+            let near = |v: f32, lo: f32, hi: f32| (lo - 0.01..=hi + 0.01).contains(&v);
+            assert!(near(fg, 0.9, 1.0), "{:?} fg {}", accent, fg);
+            assert!(near(bg, 0.0, 0.1), "{:?} bg {}", accent, bg);
+            assert!(near(acc, 0.5, 0.85), "{:?} acc {}", accent, acc);
+            assert!(near(hover, 0.6, 0.9), "{:?} hover {}", accent, hover);
+            assert!(near(selection, 0.25, 0.35), "{:?} selection {}", accent, selection);
+            // text stays readable on every fill it's drawn over
+            assert!(
+                fg - selection > 0.5 && acc - bg > 0.35 && hover - bg > 0.45,
+                "{:?}",
+                accent
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Reference:
+// https://specifications.freedesktop.org/desktop-entry-spec/desktop-entry-spec-latest.html
+
+use std::collections::HashMap;
+
+pub const MAIN_GROUP: &str = "Desktop Entry";
+
+/// The groups of a desktop entry file. Values are raw, so strings still carry their escapes
+#[derive(Default)]
+pub struct DesktopEntry {
+    groups: HashMap<String, HashMap<String, String>>,
+}
+
+/// Read every `[Group]` in any order. Comments, blank lines, and keys before the first group are skipped,
+/// and a repeated key keeps its last value
+pub fn parse_entry(data: &str) -> DesktopEntry {
+    let mut group = None;
+    let mut groups = HashMap::<String, HashMap<String, String>>::new();
+    // lines() already drops the \r of CRLF endings
+    for line in data.strip_prefix('\u{feff}').unwrap_or(data).lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            continue;
+        } else if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            group = Some(trimmed[1..trimmed.len() - 1].to_string());
+        } else if let Some(group) = group.as_ref() {
+            if let Some((a, b)) = line.split_once("=") {
+                groups
+                    .entry(group.clone())
+                    .or_default()
+                    .insert(a.trim().to_string(), b.trim_start().to_string());
+            }
+        }
+    }
+    DesktopEntry { groups }
+}
+
+impl DesktopEntry {
+    /// Raw value, for keys like Exec with their own quoting rules
+    pub fn raw(&self, group: &str, key: &str) -> Option<&str> {
+        self.groups.get(group)?.get(key).map(|s| s.as_str())
+    }
+
+    /// Unescaped string value
+    pub fn string(&self, group: &str, key: &str) -> Option<String> {
+        self.raw(group, key).map(unescape_value)
+    }
+
+    /// Only `true` and `false` are valid booleans
+    pub fn boolean(&self, group: &str, key: &str) -> Option<bool> {
+        self.raw(group, key).and_then(|s| s.parse::<bool>().ok())
+    }
+
+    /// Unescaped string list, empty if missing
+    pub fn list(&self, group: &str, key: &str) -> Vec<String> {
+        self.raw(group, key).map(parse_list).unwrap_or_default()
+    }
+}
+
+/// Split a desktop entry string list on `;`, dropping empties from the trailing separator.
+/// `\;` is a literal semicolon and the other string escapes apply to each item
+pub fn parse_list(s: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let item = items.last_mut().unwrap();
+        match (c, chars.clone().next()) {
+            ('\\', Some(';')) => {
+                item.push(';');
+                chars.next();
+            }
+            ('\\', Some(next)) => {
+                item.push('\\');
+                item.push(next);
+                chars.next();
+            }
+            (';', _) => items.push(String::new()),
+            (c, _) => item.push(c),
+        }
+    }
+    items
+        .iter()
+        .map(|s| unescape_value(s.trim()))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Apply the desktop entry string escapes `\s \n \t \r \\`, leaving unknown escapes as-is
+pub fn unescape_value(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            Some(c) => {
+                result.push('\\');
+                result.push(c)
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape_value(r"Foo\sBar"), "Foo Bar");
+        assert_eq!(unescape_value(r"a\nb\tc\rd"), "a\nb\tc\rd");
+        assert_eq!(unescape_value(r"back\\slash\\s"), r"back\slash\s");
+        // unknown and trailing escapes are kept
+        assert_eq!(unescape_value(r"\q\"), r"\q\");
+        assert_eq!(parse_list(r"one;two\;three;\sfour;;"), ["one", "two;three", " four"]);
+    }
+
+    #[test]
+    fn crlf_and_bom() {
+        let entry = parse_entry(
+            "\u{feff}[Desktop Entry]\r\nName=Wine\\sApp\r\nComment=Line\\none\r\n\
+             Keywords=game\\sfoo;bar;\r\nExec=env WINEPREFIX=\"/home/me/.wine\" wine C:\\\\\\\\x.exe\r\n\
+             NoDisplay=true\r\n",
+        );
+        assert_eq!(entry.string(MAIN_GROUP, "Name").as_deref(), Some("Wine App"));
+        assert_eq!(entry.string(MAIN_GROUP, "Comment").as_deref(), Some("Line\none"));
+        assert_eq!(entry.list(MAIN_GROUP, "Keywords"), ["game foo", "bar"]);
+        // left for the Exec tokenizer
+        assert_eq!(
+            entry.raw(MAIN_GROUP, "Exec"),
+            Some(r#"env WINEPREFIX="/home/me/.wine" wine C:\\\\x.exe"#)
+        );
+        assert_eq!(entry.boolean(MAIN_GROUP, "NoDisplay"), Some(true));
+    }
+
+    #[test]
+    fn groups_and_comments() {
+        let entry = parse_entry(
+            "Orphan=1\n# [Desktop Entry]\n[Desktop Action new]\nName=New\n\n[Desktop Entry]\n  # Name=Commented\n\
+             Name=First\nName = Last\nHidden=yes\n",
+        );
+        assert_eq!(entry.raw(MAIN_GROUP, "Orphan"), None);
+        assert_eq!(entry.string("Desktop Action new", "Name").as_deref(), Some("New"));
+        assert_eq!(entry.string(MAIN_GROUP, "Name").as_deref(), Some("Last"));
+        // only true and false are booleans
+        assert_eq!(entry.boolean(MAIN_GROUP, "Hidden"), None);
+        assert!(entry.list(MAIN_GROUP, "Missing").is_empty());
+    }
+}
@@ -0,0 +1,863 @@
+// Reference:
+// https://specifications.freedesktop.org/basedir-spec/basedir-spec-latest.html
+// https://specifications.freedesktop.org/desktop-entry-spec/desktop-entry-spec-latest.html
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs::read_to_string;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::desktop;
+
+/// Text within a shared buffer, so a whole dmenu input can back every name
+/// with one allocation and clones only bump a reference count
+#[derive(Clone, Default)]
+pub struct Name {
+    // {{{
+    buf: Arc<str>,
+    start: usize,
+    end: usize,
+}
+
+impl Name {
+    /// `part` must be a subslice of `buf`
+    pub fn within(buf: &Arc<str>, part: &str) -> Self {
+        let start = (part.as_ptr() as usize).wrapping_sub(buf.as_ptr() as usize);
+        assert!(
+            start <= buf.len() && part.len() <= buf.len() - start,
+            "name outside of its buffer"
+        );
+        Self {
+            buf: buf.clone(),
+            start,
+            end: start + part.len(),
+        }
+    }
+}
+
+impl std::ops::Deref for Name {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.buf[self.start..self.end]
+    }
+}
+
+impl From<&str> for Name {
+    fn from(value: &str) -> Self {
+        Arc::<str>::from(value).into()
+    }
+}
+
+impl From<String> for Name {
+    fn from(value: String) -> Self {
+        Arc::<str>::from(value).into()
+    }
+}
+
+impl From<Arc<str>> for Name {
+    fn from(buf: Arc<str>) -> Self {
+        Self {
+            end: buf.len(),
+            buf,
+            start: 0,
+        }
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Name {}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        &**self == *other
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl std::hash::Hash for Name {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl std::fmt::Debug for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+} // }}}
+
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Item {
+    /// Shared so cloning items for sorting and filtering stays cheap on huge menus
+    pub name: Name,
+    pub file: Option<PathBuf>,
+    /// Desktop file ID from where the file was found, such as kde4-foo.desktop for kde4/foo.desktop
+    pub id: Option<String>,
+    pub exec: Option<String>,
+    /// TryExec, a program that must be installed for the entry to be shown
+    pub try_exec: Option<String>,
+    pub path: Option<PathBuf>,
+    pub icon: Option<String>,
+    /// NoDisplay=true, meant for other desktops, or its TryExec is missing.
+    /// Only listed with --all or Ctrl+H and drawn dimmed
+    pub no_display: bool,
+    /// Hidden=true, the entry is deleted and only shadows others of the same ID
+    pub deleted: bool,
+    pub only_show_in: Vec<String>,
+    pub not_show_in: Vec<String>,
+    pub keywords: Vec<String>,
+    pub terminal: bool,
+    /// Activated over D-Bus by its desktop file ID rather than spawned
+    pub dbus_activatable: bool,
+    /// PrefersNonDefaultGPU, run on the discrete GPU when there is one
+    pub prefers_dgpu: bool,
+    pub comment: Option<String>,
+    pub categories: Vec<String>,
+    /// MimeType, what the entry can open
+    pub mime_types: Vec<String>,
+    /// Desktop Action ID if this is an action of another entry
+    pub action: Option<String>,
+    pub actions: Vec<Item>,
+    /// Target of a Type=Link entry
+    pub url: Option<String>,
+    /// Short text drawn at the right edge of the cell
+    pub badge: Option<String>,
+    /// Drawn in the detail column, such as the path or Exec
+    pub detail: Option<String>,
+    /// Zero based stdin line in dmenu mode
+    pub line: Option<usize>,
+    /// An unselectable divider before a section, labeled by `name`
+    pub separator: bool,
+    /// Sorts above unranked items, lowest first, regardless of the cache
+    pub rank: Option<usize>,
+}
+
+/// Why a file under an applications dir didn't become an item
+pub enum EntryError {
+    /// Not named .desktop, so never an entry
+    NotDesktop,
+    Unreadable(std::io::Error),
+    NoName,
+    LinkWithoutUrl,
+}
+
+impl std::fmt::Display for EntryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotDesktop => write!(f, "skipped: not a .desktop file"),
+            Self::Unreadable(e) => write!(f, "error: {}", e),
+            Self::NoName => write!(f, "skipped: no Name"),
+            Self::LinkWithoutUrl => write!(f, "error: Type=Link without URL"),
+        }
+    }
+}
+
+impl Item {
+    /// None for paths without a file name
+    pub fn from_path(path: PathBuf) -> Option<Self> {
+        let fname = path.file_name().map(|osstr| osstr.to_string_lossy().to_string());
+        fname.map(|name| Self {
+            name: name.into(),
+            file: Some(path),
+            ..Default::default()
+        })
+    }
+    pub fn from_desktop(path: PathBuf) -> Result<Self, EntryError> {
+        // {{{
+        if path.extension() == Some(OsString::from("desktop").as_os_str()) {
+            match read_to_string(&path) {
+                Ok(data) => {
+                    let entry = desktop::parse_entry(&data);
+                    let main = desktop::MAIN_GROUP;
+                    // deleted entries usually carry nothing else, but must still shadow lower precedence dirs
+                    if entry.raw(main, "Hidden") == Some("true") {
+                        return Ok(Self {
+                            file: Some(path),
+                            deleted: true,
+                            ..Default::default()
+                        });
+                    }
+                    let url = entry.string(main, "URL").filter(|s| !s.is_empty());
+                    // links without a URL are malformed
+                    if entry.raw(main, "Type") == Some("Link") && url.is_none() {
+                        return Err(EntryError::LinkWithoutUrl);
+                    }
+                    if let Some(name) = entry.string(main, "Name") {
+                        let mut item = Self {
+                            name: name.into(),
+                            file: Some(path),
+                            id: None,
+                            exec: entry.raw(main, "Exec").map(|s| s.to_string()),
+                            try_exec: entry.string(main, "TryExec").filter(|s| !s.is_empty()),
+                            icon: entry.raw(main, "Icon").map(|s| s.to_string()),
+                            path: entry.raw(main, "Path").map(expand_path),
+                            no_display: entry.boolean(main, "NoDisplay").unwrap_or(false),
+                            deleted: false,
+                            only_show_in: entry.list(main, "OnlyShowIn"),
+                            not_show_in: entry.list(main, "NotShowIn"),
+                            keywords: entry.list(main, "Keywords"),
+                            terminal: entry.boolean(main, "Terminal").unwrap_or(false),
+                            dbus_activatable: entry.boolean(main, "DBusActivatable").unwrap_or(false),
+                            prefers_dgpu: entry.boolean(main, "PrefersNonDefaultGPU").unwrap_or(false),
+                            comment: entry.string(main, "Comment").filter(|s| !s.is_empty()),
+                            categories: entry.list(main, "Categories"),
+                            mime_types: entry.list(main, "MimeType"),
+                            action: None,
+                            actions: Vec::new(),
+                            url,
+                            badge: None,
+                            detail: None,
+                            line: None,
+                            separator: false,
+                            rank: None,
+                        };
+                        // only actions listed in Actions= are valid
+                        item.actions = entry
+                            .list(main, "Actions")
+                            .into_iter()
+                            .filter_map(|id| {
+                                let group = String::from("Desktop Action ") + &id;
+                                Some(Self {
+                                    name: entry.string(&group, "Name")?.into(),
+                                    file: item.file.clone(),
+                                    exec: entry.raw(&group, "Exec").map(|s| s.to_string()),
+                                    path: item.path.clone(),
+                                    icon: entry.raw(&group, "Icon").map(|s| s.to_string()).or(item.icon.clone()),
+                                    terminal: item.terminal,
+                                    dbus_activatable: item.dbus_activatable,
+                                    prefers_dgpu: item.prefers_dgpu,
+                                    action: Some(id),
+                                    ..Default::default()
+                                })
+                            })
+                            .collect();
+                        Ok(item)
+                    } else {
+                        Err(EntryError::NoName)
+                    }
+                }
+                Err(e) => Err(EntryError::Unreadable(e)),
+            }
+        } else {
+            Err(EntryError::NotDesktop)
+        }
+    } // }}}
+
+    /// Whether the name or any keyword satisfies the predicate
+    pub fn matches(&self, f: impl Fn(&str) -> bool) -> bool {
+        f(self.as_ref()) || self.keywords.iter().any(|k| f(k))
+    }
+
+    /// Whether OnlyShowIn/NotShowIn permit this entry in any of the given desktops
+    pub fn shown_in(&self, desktops: &[String]) -> bool {
+        if desktops.iter().any(|d| self.not_show_in.contains(d)) {
+            false
+        } else {
+            self.only_show_in.is_empty() || desktops.iter().any(|d| self.only_show_in.contains(d))
+        }
+    }
+}
+
+impl AsRef<str> for Item {
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+pub fn get_binaries() -> Vec<Item> {
+    env::var_os("PATH")
+        .map(|paths| binaries_in(env::split_paths(&paths)))
+        .unwrap_or_default()
+}
+
+/// Every executable under the given directories, once per name.
+/// The first directory wins like in a shell, so a name shadowed by an earlier one is skipped.
+/// Different names for the same file, such as python3 and python3.12, are all kept
+pub fn binaries_in(directories: impl Iterator<Item = PathBuf>) -> Vec<Item> {
+    // {{{
+    let mut binaries = Vec::new();
+    let mut seen = HashSet::new();
+    for directory in directories {
+        for entry in WalkDir::new(directory).follow_links(true).into_iter().flatten() {
+            if let Ok(meta) = entry.metadata() {
+                let bit = 0b1;
+                if !meta.is_dir() && meta.permissions().mode() & bit == bit {
+                    let path = entry.into_path();
+                    if let Some(item) = Item::from_path(path).filter(|i| seen.insert(i.name.clone())) {
+                        binaries.push(item);
+                    }
+                }
+            }
+        }
+    }
+    binaries
+} // }}}
+
+/// Expand a leading `~` and `$VAR` or `${VAR}` references like a shell would.
+/// Undefined variables expand to nothing
+fn expand_path(s: &str) -> PathBuf {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    if rest == "~" || rest.starts_with("~/") {
+        result.push_str(&env::var("HOME").unwrap_or_default());
+        rest = &rest[1..];
+    }
+    while let Some(n) = rest.find('$') {
+        result.push_str(&rest[..n]);
+        rest = &rest[n + 1..];
+        let (name, tail) = if let Some((name, tail)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            (name, tail)
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        };
+        if name.is_empty() {
+            result.push('$');
+        } else {
+            result.push_str(&env::var(name).unwrap_or_default());
+        }
+        rest = tail;
+    }
+    result.push_str(rest);
+    PathBuf::from(result)
+}
+
+/// Whether an entry listing `types` opens `mime`, allowing `type/*` in the list
+fn opens_mime(types: &[String], mime: &str) -> bool {
+    types.iter().any(|t| {
+        t.eq_ignore_ascii_case(mime)
+            || t.strip_suffix("/*")
+                .is_some_and(|major| mime.split('/').next().is_some_and(|m| m.eq_ignore_ascii_case(major)))
+    })
+}
+
+/// mimeapps.list files from highest precedence, each dir's desktop specific ones before its generic one
+fn mimeapps_files() -> Vec<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+    let config_dirs = env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .unwrap_or(String::from("/etc/xdg"));
+    let desktops: Vec<String> = env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_lowercase())
+        .collect();
+    config_home
+        .into_iter()
+        .chain(config_dirs.split(':').filter(|d| !d.is_empty()).map(PathBuf::from))
+        .chain(data_dirs().into_iter().map(|d| d.join("applications")))
+        .flat_map(|dir| {
+            desktops
+                .iter()
+                .map(|d| dir.join(format!("{}-mimeapps.list", d)))
+                .chain(std::iter::once(dir.join("mimeapps.list")))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Desktop IDs associated with `mime` by mimeapps.list, defaults before added associations,
+/// and the IDs whose own association was removed
+fn mime_handlers(mime: &str) -> (Vec<String>, HashSet<String>) {
+    let mut defaults = Vec::<String>::new();
+    let mut added = Vec::<String>::new();
+    let mut removed = HashSet::<String>::new();
+    for file in mimeapps_files() {
+        let Ok(data) = read_to_string(&file) else {
+            continue;
+        };
+        let entry = desktop::parse_entry(&data);
+        // removals only reach files of lower precedence
+        for id in entry.list("Default Applications", mime) {
+            if !removed.contains(&id) && !defaults.contains(&id) {
+                defaults.push(id)
+            }
+        }
+        for id in entry.list("Added Associations", mime) {
+            if !removed.contains(&id) && !added.contains(&id) {
+                added.push(id)
+            }
+        }
+        removed.extend(entry.list("Removed Associations", mime));
+    }
+    added.retain(|id| !defaults.contains(id));
+    defaults.extend(added);
+    (defaults, removed)
+}
+
+/// XDG_DATA_HOME followed by XDG_DATA_DIRS then flatpak and snap exports, highest precedence first
+pub fn data_dirs() -> Vec<PathBuf> {
+    // empty counts as unset per the basedir spec
+    let var = |name: &str| env::var_os(name).filter(|v| !v.is_empty());
+    // without either there's no user dir, only the system ones
+    let mut dirs: Vec<PathBuf> = var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(var("HOME")?).join(".local/share")))
+        .into_iter()
+        .collect();
+    let system = var("XDG_DATA_DIRS").unwrap_or_else(|| OsString::from("/usr/local/share/:/usr/share/"));
+    // exports are often missing from XDG_DATA_DIRS without a login shell
+    let mut exports = vec![
+        PathBuf::from("/var/lib/flatpak/exports/share"),
+        PathBuf::from("/var/lib/snapd/desktop"),
+    ];
+    if let Ok(home) = env::var("HOME") {
+        exports.insert(0, PathBuf::from(home + "/.local/share/flatpak/exports/share"))
+    }
+    // Nix profiles tend to repeat entries, which would otherwise be read again for every lookup
+    for dir in env::split_paths(&system)
+        .filter(|d| !d.as_os_str().is_empty())
+        .chain(exports)
+    {
+        if !dirs.iter().any(|d| d.components().eq(dir.components())) {
+            dirs.push(dir)
+        }
+    }
+    dirs
+}
+
+pub fn get_applications(include_hidden: bool, categories: &[String], mime: Option<&str>, verbose: bool) -> Vec<Item> {
+    applications_in(data_dirs(), include_hidden, categories, mime, verbose)
+}
+
+/// Entries under the `applications` dir of each data dir, highest precedence first.
+/// With `verbose` what became of every file is written to stderr
+pub fn applications_in(
+    data_dirs: Vec<PathBuf>,
+    include_hidden: bool,
+    categories: &[String],
+    mime: Option<&str>,
+    verbose: bool,
+) -> Vec<Item> {
+    // {{{
+    // add them in backwards because the desktop entry spec
+    // states it should return the first found
+    let paths = data_dirs.into_iter().rev();
+
+    let desktops = current_desktops(env::var("XDG_CURRENT_DESKTOP").ok().as_deref());
+
+    // keyed by desktop file ID so higher precedence dirs replace earlier entries
+    let mut result = HashMap::<String, Item>::new();
+    let mut report = Vec::<(PathBuf, String)>::new();
+    let shadowed = |by: &Item| format!("shadowed by {}", by.file.as_deref().unwrap_or(Path::new("")).display());
+    #[cfg(debug_assertions)]
+    let mut duplicates = 0;
+
+    // walked in order first so only the parsing, which is most of the time, runs in parallel
+    let walked: Vec<Vec<(String, PathBuf)>> = paths
+        .map(|mut path| {
+            path.push("applications");
+            // within one dir a top level kde4-foo.desktop beats kde4/foo.desktop,
+            // so files are walked before subdirs and the first of an ID is kept
+            WalkDir::new(&path)
+                .follow_links(true)
+                .sort_by(|a, b| (a.file_type().is_dir(), a.file_name()).cmp(&(b.file_type().is_dir(), b.file_name())))
+                .into_iter()
+                .flatten()
+                .filter(|entry| !entry.file_type().is_dir())
+                .filter_map(|entry| Some((desktop_id(&path, entry.path())?, entry.into_path())))
+                .collect()
+        })
+        .collect();
+    let parsed: Vec<Vec<Result<Item, EntryError>>> = walked
+        .par_iter()
+        .map(|dir| {
+            dir.par_iter()
+                .map(|(_, file)| Item::from_desktop(file.clone()))
+                .collect()
+        })
+        .collect();
+
+    for (dir, parsed) in walked.into_iter().zip(parsed) {
+        let mut found = HashMap::<String, Item>::new();
+        for ((id, file), parsed) in dir.into_iter().zip(parsed) {
+            if let Some(kept) = found.get(&id) {
+                if verbose {
+                    report.push((file, shadowed(kept)))
+                }
+                continue;
+            }
+            match parsed {
+                Ok(mut item) => {
+                    for action in item.actions.iter_mut() {
+                        action.id = Some(id.clone())
+                    }
+                    item.id = Some(id.clone());
+                    found.insert(id, item);
+                }
+                Err(EntryError::NotDesktop) => (),
+                Err(e) => {
+                    if verbose {
+                        report.push((file, e.to_string()))
+                    }
+                }
+            }
+        }
+        for (id, item) in found {
+            let file = item.file.clone();
+            if let Some(old) = result.insert(id, item) {
+                if let (true, Some(old)) = (verbose, old.file) {
+                    report.push((old, format!("shadowed by {}", file.unwrap_or_default().display())))
+                }
+                #[cfg(debug_assertions)]
+                {
+                    duplicates += 1
+                }
+            }
+        }
+    }
+    // stderr, as stdout may be --list --json or the selection
+    #[cfg(debug_assertions)]
+    eprintln!("{} duplicate desktop entries collapsed", duplicates);
+
+    let (handlers, removed) = mime.map(mime_handlers).unwrap_or_default();
+    let mut items = Vec::new();
+    for (id, mut item) in result {
+        // reported either way, include_hidden only decides if it's kept
+        let hidden = hidden_reason(&item, mime.is_some(), &desktops);
+        let skip = if item.deleted {
+            Some("skipped: Hidden".to_string())
+        } else if let (false, Some(hidden)) = (include_hidden, hidden) {
+            Some(format!("skipped: {}", hidden))
+        } else if !categories.is_empty()
+            && !item
+                .categories
+                .iter()
+                .any(|c| categories.iter().any(|f| f.eq_ignore_ascii_case(c)))
+        {
+            Some("skipped: not in the requested categories".to_string())
+        } else if mime
+            .is_some_and(|m| !handlers.contains(&id) && (removed.contains(&id) || !opens_mime(&item.mime_types, m)))
+        {
+            Some("skipped: MimeType doesn't include the requested type".to_string())
+        } else {
+            None
+        };
+        if let (true, Some(file)) = (verbose, item.file.as_ref()) {
+            let decision = match (&skip, hidden) {
+                (Some(skip), _) => skip.clone(),
+                (None, Some(hidden)) => format!("parsed OK, hidden: {}", hidden),
+                (None, None) => "parsed OK".to_string(),
+            };
+            report.push((file.clone(), decision))
+        }
+        if skip.is_none() {
+            item.no_display = hidden.is_some();
+            // default handlers first
+            item.rank = handlers.iter().position(|h| *h == id);
+            items.push(item)
+        }
+    }
+    report.sort();
+    for (file, decision) in report {
+        eprintln!("{}: {}", file.display(), decision)
+    }
+    items
+} // }}}
+
+/// XDG_CURRENT_DESKTOP is a colon separated list, any of which may match OnlyShowIn/NotShowIn
+fn current_desktops(var: Option<&str>) -> Vec<String> {
+    var.unwrap_or_default()
+        .split(':')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Why an entry is only listed with --all, if it is
+fn hidden_reason(item: &Item, mime: bool, desktops: &[String]) -> Option<&'static str> {
+    if item.deleted {
+        None
+    // handlers are often NoDisplay as they're only meant for opening files
+    } else if item.no_display && !mime {
+        Some("NoDisplay")
+    } else if !item.shown_in(desktops) {
+        Some("OnlyShowIn/NotShowIn")
+    } else if !item.try_exec.as_deref().is_none_or(on_path) {
+        Some("TryExec not found")
+    } else {
+        None
+    }
+}
+
+/// Whether `program` is an executable file, looked up in PATH unless absolute
+fn on_path(program: &str) -> bool {
+    let executable = |path: &Path| {
+        path.metadata()
+            .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if Path::new(program).is_absolute() {
+        executable(Path::new(program))
+    } else {
+        env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| executable(&dir.join(program))))
+    }
+}
+
+/// Desktop file ID, the path relative to an applications dir with `/` replaced by `-`
+pub fn desktop_id(base: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(base)
+        .ok()
+        .map(|rel| rel.to_string_lossy().replace('/', "-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A data dir holding `entries` under applications/, unique per test
+    fn data_dir(test: &str, entries: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("linch-discovery-{}-{}", test, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        for (name, data) in entries {
+            let file = dir.join("applications").join(name);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, data).unwrap();
+        }
+        dir
+    }
+
+    fn names(items: &[Item]) -> Vec<&str> {
+        let mut names: Vec<&str> = items.iter().map(|i| &*i.name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn mime_includes_no_display() {
+        let dir = data_dir(
+            "mime",
+            &[
+                (
+                    "viewer.desktop",
+                    "[Desktop Entry]\nType=Application\nName=Viewer\nExec=viewer %u\nMimeType=application/pdf;\nNoDisplay=true\n",
+                ),
+                (
+                    "editor.desktop",
+                    "[Desktop Entry]\nType=Application\nName=Editor\nExec=editor %f\nMimeType=text/plain;\n",
+                ),
+            ],
+        );
+        let items = applications_in(vec![dir.clone()], false, &[], Some("application/pdf"), false);
+        assert_eq!(names(&items), ["Viewer"]);
+        assert!(!items[0].no_display);
+        assert!(applications_in(vec![dir.clone()], false, &[], None, false)
+            .iter()
+            .all(|i| &*i.name != "Viewer"));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn nested_ids() {
+        let dir = data_dir(
+            "ids",
+            &[(
+                "kde4/org.kde.foo.desktop",
+                "[Desktop Entry]\nType=Application\nName=Foo\nExec=foo\nActions=new;\n\n\
+                 [Desktop Action new]\nName=New Foo\nExec=foo --new\n",
+            )],
+        );
+        let items = applications_in(vec![dir.clone()], false, &[], None, false);
+        assert_eq!(items[0].id.as_deref(), Some("kde4-org.kde.foo.desktop"));
+        assert_eq!(items[0].actions[0].id.as_deref(), Some("kde4-org.kde.foo.desktop"));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn show_in_desktops() {
+        assert_eq!(current_desktops(Some("ubuntu:GNOME")), ["ubuntu", "GNOME"]);
+        assert_eq!(current_desktops(Some("sway:")), ["sway"]);
+        assert!(current_desktops(Some("")).is_empty());
+        assert!(current_desktops(None).is_empty());
+        let item = |only: &[&str], not: &[&str]| Item {
+            only_show_in: only.iter().map(|s| s.to_string()).collect(),
+            not_show_in: not.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        let shown = |item: &Item, var| item.shown_in(&current_desktops(Some(var)));
+        // any of the current desktops is enough for OnlyShowIn
+        assert!(shown(&item(&["GNOME"], &[]), "ubuntu:GNOME"));
+        assert!(!shown(&item(&["GNOME"], &[]), "sway"));
+        assert!(!shown(&item(&["GNOME"], &[]), ""));
+        assert!(!shown(&item(&[], &["KDE"]), "sway:KDE"));
+        assert!(shown(&item(&[], &["KDE"]), ""));
+        // with both keys NotShowIn wins for a desktop in each
+        assert!(!shown(&item(&["GNOME"], &["Unity"]), "Unity:GNOME"));
+        assert!(shown(&item(&["GNOME"], &["Unity"]), "ubuntu:GNOME"));
+        assert!(!shown(&item(&["GNOME"], &["Unity"]), "KDE"));
+    }
+
+    #[test]
+    fn path_expansion() {
+        let home = env::var("HOME").unwrap_or_default();
+        assert_eq!(expand_path("~"), PathBuf::from(&home));
+        assert_eq!(
+            expand_path("~/projects/foo"),
+            PathBuf::from(format!("{}/projects/foo", home))
+        );
+        assert_eq!(
+            expand_path("$HOME/games/bar"),
+            PathBuf::from(format!("{}/games/bar", home))
+        );
+        assert_eq!(expand_path("${HOME}bar"), PathBuf::from(format!("{}bar", home)));
+        // only a leading tilde is the home dir
+        assert_eq!(expand_path("/opt/~user"), PathBuf::from("/opt/~user"));
+        assert_eq!(expand_path("/opt/$LINCH_TEST_UNDEFINED/x"), PathBuf::from("/opt//x"));
+        assert_eq!(expand_path("/opt/${LINCH_TEST_UNDEFINED}x"), PathBuf::from("/opt/x"));
+        assert_eq!(expand_path("/opt/$/x"), PathBuf::from("/opt/$/x"));
+        assert_eq!(expand_path("/usr/share/My Games"), PathBuf::from("/usr/share/My Games"));
+    }
+
+    #[test]
+    fn colliding_ids() {
+        let entry = |name: &str| format!("[Desktop Entry]\nType=Application\nName={}\nExec=foo\n", name);
+        let low = data_dir(
+            "collide-low",
+            &[
+                ("kde4/foo.desktop", &entry("Nested")),
+                ("kde4-foo.desktop", &entry("Top")),
+                ("kde4/bar.desktop", &entry("Bar")),
+            ],
+        );
+        // only the applications subtree holds entries
+        std::fs::write(low.join("stray.desktop"), entry("Stray")).unwrap();
+        std::fs::create_dir_all(low.join("other")).unwrap();
+        std::fs::write(low.join("other/stray.desktop"), entry("Stray")).unwrap();
+        let items = applications_in(vec![low.clone()], false, &[], None, false);
+        // within a dir the top level file beats the subdir one of the same ID
+        assert_eq!(names(&items), ["Bar", "Top"]);
+        assert!(items.iter().any(|i| i.id.as_deref() == Some("kde4-bar.desktop")));
+
+        // a higher precedence dir wins whichever form it uses
+        let high = data_dir("collide-high", &[("kde4/foo.desktop", &entry("High"))]);
+        let items = applications_in(vec![high.clone(), low.clone()], false, &[], None, false);
+        assert_eq!(names(&items), ["Bar", "High"]);
+        let items = applications_in(vec![low.clone(), high.clone()], false, &[], None, false);
+        assert_eq!(names(&items), ["Bar", "Top"]);
+        std::fs::remove_dir_all(low).ok();
+        std::fs::remove_dir_all(high).ok();
+    }
+
+    #[test]
+    fn user_hidden_shadows_system() {
+        let user = data_dir("deleted-user", &[("foo.desktop", "[Desktop Entry]\nHidden=true\n")]);
+        let system = data_dir(
+            "deleted-system",
+            &[
+                ("foo.desktop", "[Desktop Entry]\nType=Application\nName=Foo\nExec=foo\n"),
+                ("bar.desktop", "[Desktop Entry]\nType=Application\nName=Bar\nExec=bar\n"),
+            ],
+        );
+        // deleted rather than hidden, so not even --all brings it back
+        for include_hidden in [false, true] {
+            let items = applications_in(vec![user.clone(), system.clone()], include_hidden, &[], None, false);
+            assert_eq!(names(&items), ["Bar"]);
+        }
+        let items = applications_in(vec![system.clone()], false, &[], None, false);
+        assert_eq!(names(&items), ["Bar", "Foo"]);
+        std::fs::remove_dir_all(user).ok();
+        std::fs::remove_dir_all(system).ok();
+    }
+
+    #[test]
+    fn hidden_reasons() {
+        let desktops = ["GNOME".to_string()];
+        let item = |f: fn(&mut Item)| {
+            let mut item = Item::default();
+            f(&mut item);
+            item
+        };
+        let reason = |item: &Item, mime| hidden_reason(item, mime, &desktops);
+        assert_eq!(reason(&item(|_| ()), false), None);
+        assert_eq!(reason(&item(|i| i.no_display = true), false), Some("NoDisplay"));
+        assert_eq!(reason(&item(|i| i.no_display = true), true), None);
+        assert_eq!(
+            reason(&item(|i| i.only_show_in = vec!["KDE".into()]), false),
+            Some("OnlyShowIn/NotShowIn")
+        );
+        assert_eq!(
+            reason(&item(|i| i.not_show_in = vec!["GNOME".into()]), false),
+            Some("OnlyShowIn/NotShowIn")
+        );
+        assert_eq!(
+            reason(&item(|i| i.try_exec = Some("/nonexistent/linch-test".into())), false),
+            Some("TryExec not found")
+        );
+        assert_eq!(reason(&item(|i| i.try_exec = Some("/bin/sh".into())), false), None);
+    }
+
+    #[test]
+    fn all_keeps_hidden_dimmed() {
+        let dir = data_dir(
+            "all",
+            &[
+                (
+                    "shown.desktop",
+                    "[Desktop Entry]\nType=Application\nName=Shown\nExec=shown\n",
+                ),
+                (
+                    "nodisplay.desktop",
+                    "[Desktop Entry]\nType=Application\nName=NoDisplay\nExec=nd\nNoDisplay=true\n",
+                ),
+                (
+                    "tryexec.desktop",
+                    "[Desktop Entry]\nType=Application\nName=TryExec\nExec=te\nTryExec=/nonexistent/linch-test\n",
+                ),
+                (
+                    "deleted.desktop",
+                    "[Desktop Entry]\nType=Application\nName=Deleted\nExec=del\nHidden=true\n",
+                ),
+            ],
+        );
+        let items = applications_in(vec![dir.clone()], false, &[], None, false);
+        assert_eq!(names(&items), ["Shown"]);
+        let items = applications_in(vec![dir.clone()], true, &[], None, false);
+        assert_eq!(names(&items), ["NoDisplay", "Shown", "TryExec"]);
+        assert!(items.iter().all(|i| i.no_display == (&*i.name != "Shown")));
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
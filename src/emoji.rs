@@ -0,0 +1,24 @@
+// The table for `linch emoji`, one `glyph\tname` per line from the Unicode 14 character names
+// of the emoji blocks, misc symbols, and dingbats. Symbols that default to text get U+FE0F.
+// Sequences such as flags and skin tones aren't included.
+
+use linch::discovery::Item;
+
+const TABLE: &str = include_str!("emoji.tsv");
+
+/// Every entry as `glyph name`, so searching matches the name
+pub fn items() -> Vec<Item> {
+    TABLE
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(glyph, name)| Item {
+            name: format!("{} {}", glyph, name).into(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The glyph of an item from `items`
+pub fn glyph(item: &Item) -> &str {
+    item.name.split_once(' ').map_or(&item.name, |(glyph, _)| glyph)
+}
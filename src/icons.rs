@@ -0,0 +1,612 @@
+// Reference:
+// https://specifications.freedesktop.org/icon-theme-spec/icon-theme-spec-latest.html
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Preferred first. XPM is mostly left in pixmaps by older packages, and ICO by Wine
+const EXTENSIONS: [&str; 5] = ["svg", "png", "xpm", "ico", "gif"];
+
+/// `Icon=app.png` and `Icon=app.PNG` name the same icon as `Icon=app`
+fn strip_extension(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)) => stem,
+        _ => name,
+    }
+}
+
+/// Base directories containing themes, highest precedence first
+pub fn icon_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if let Ok(home) = env::var("HOME") {
+        roots.push(PathBuf::from(home + "/.icons"))
+    }
+    roots.extend(crate::discovery::data_dirs().into_iter().map(|d| d.join("icons")));
+    roots
+}
+
+/// Icon theme set in the GTK 4 or 3 settings, else the GNOME setting if gsettings is installed
+pub fn gtk_theme() -> Option<String> {
+    let config = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| PathBuf::from(h).join(".config")));
+    let from_ini = |version: &str| {
+        read_to_string(config.as_ref().ok()?.join(version).join("settings.ini"))
+            .ok()?
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .find(|(k, _)| k.trim() == "gtk-icon-theme-name")
+            .map(|(_, v)| v.trim().trim_matches('"').to_string())
+    };
+    let from_gsettings = || {
+        let output = std::process::Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()
+            .filter(|o| o.status.success())?;
+        Some(
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .trim_matches('\'')
+                .to_string(),
+        )
+    };
+    from_ini("gtk-4.0")
+        .or_else(|| from_ini("gtk-3.0"))
+        .or_else(from_gsettings)
+        .filter(|v| !v.is_empty())
+}
+
+/// `[Group]` -> key -> value for index.theme style files
+fn parse_ini(data: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut groups = HashMap::<String, HashMap<String, String>>::new();
+    let mut group = None;
+    for line in data.lines().map(|l| l.trim()) {
+        if line.starts_with('[') && line.ends_with(']') {
+            group = Some(line[1..line.len() - 1].to_string());
+        } else if let (Some(group), Some((k, v))) = (group.as_ref(), line.split_once('=')) {
+            groups
+                .entry(group.clone())
+                .or_default()
+                .insert(k.trim().to_string(), v.trim().to_string());
+        }
+    }
+    groups
+}
+
+/// Sizes of one theme subdirectory in pixels, with `Scale` applied
+struct Dir {
+    name: String,
+    size: u32,
+    min: u32,
+    max: u32,
+    scale: u32,
+    scalable: bool,
+}
+
+struct Theme {
+    /// The theme's directory under each root that has it
+    bases: Vec<PathBuf>,
+    dirs: Vec<Dir>,
+    inherits: Vec<String>,
+}
+
+impl Theme {
+    /// Only index.theme is read, icons are probed for by name when looked up
+    fn load(roots: &[PathBuf], name: &str) -> Option<Self> {
+        let bases: Vec<PathBuf> = roots.iter().map(|r| r.join(name)).filter(|b| b.is_dir()).collect();
+        let index = bases.iter().find_map(|b| read_to_string(b.join("index.theme")).ok())?;
+        let mut ini = parse_ini(&index);
+        let theme = ini.remove("Icon Theme")?;
+
+        let dirs: Vec<Dir> = theme
+            .get("Directories")
+            .into_iter()
+            .chain(theme.get("ScaledDirectories"))
+            .flat_map(|s| s.split(','))
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .map(|dir| {
+                let info = ini.get(&dir);
+                let key = |k: &str| info.and_then(|i| i.get(k)).and_then(|s| s.parse::<u32>().ok());
+                let nominal = key("Size").unwrap_or(0);
+                let scale = key("Scale").unwrap_or(1).max(1);
+                let kind = info.and_then(|i| i.get("Type")).map_or("Threshold", |t| t.as_str());
+                let (min, max) = match kind {
+                    "Fixed" => (nominal, nominal),
+                    "Scalable" => (key("MinSize").unwrap_or(nominal), key("MaxSize").unwrap_or(nominal)),
+                    _ => {
+                        let threshold = key("Threshold").unwrap_or(2);
+                        (nominal.saturating_sub(threshold), nominal + threshold)
+                    }
+                };
+                Dir {
+                    name: dir,
+                    size: nominal * scale,
+                    min: min * scale,
+                    max: max * scale,
+                    scale,
+                    scalable: kind == "Scalable",
+                }
+            })
+            .collect();
+
+        Some(Self {
+            bases,
+            dirs,
+            inherits: theme
+                .get("Inherits")
+                .map(|s| {
+                    s.split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    fn candidates(&self, name: &str) -> Vec<Candidate> {
+        let mut found = Vec::new();
+        for dir in self.dirs.iter() {
+            for base in self.bases.iter() {
+                let dir_path = base.join(&dir.name);
+                for ext in EXTENSIONS {
+                    let path = dir_path.join(format!("{}.{}", name, ext));
+                    if path.is_file() {
+                        found.push(Candidate {
+                            scalable: dir.scalable || ext == "svg",
+                            path,
+                            size: dir.size,
+                            min: dir.min,
+                            max: dir.max,
+                            scale: dir.scale,
+                        })
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Every icon name in the theme, listing each directory
+    fn names(&self) -> impl Iterator<Item = String> + '_ {
+        self.dirs
+            .iter()
+            .flat_map(|dir| self.bases.iter().map(move |base| base.join(&dir.name)))
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(|e| Some(e.ok()?.path())))
+            .filter(|path| EXTENSIONS.iter().any(|ext| path.extension() == Some(OsStr::new(ext))))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+    }
+}
+
+/// Whether SVG or raster icons win, set by --prefer-svg and --prefer-raster
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Prefer {
+    /// Rasters that are big enough, then SVG, then the largest raster
+    #[default]
+    Auto,
+    Svg,
+    Raster,
+}
+
+/// One file that could be drawn for an icon name
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// SVG or from a Scalable directory
+    pub scalable: bool,
+    /// Pixel sizes the file is meant for, `size` being the directory's nominal one
+    pub size: u32,
+    pub min: u32,
+    pub max: u32,
+    /// The directory's Scale, such as 2 for `48x48@2`
+    pub scale: u32,
+}
+
+/// The candidate to draw at `size` pixels on a `scale` times display, from those found in a single theme.
+/// Each is sorted into a class by `prefer`, then by the spec's size distance. Among equally close ones
+/// a matching Scale wins, then the nearest nominal size, then the earlier candidate
+pub fn rank(candidates: &[Candidate], size: u32, scale: u32, prefer: Prefer) -> Option<&Candidate> {
+    candidates.iter().min_by_key(|c| {
+        // rasters only stay crisp if they don't have to be scaled up
+        let fits = !c.scalable && c.max >= size;
+        let class = match prefer {
+            Prefer::Auto => [fits, c.scalable, true],
+            Prefer::Svg => [c.scalable, fits, true],
+            Prefer::Raster => [fits, !c.scalable, true],
+        }
+        .iter()
+        .position(|b| *b);
+        let distance = if size < c.min {
+            c.min - size
+        } else {
+            size.saturating_sub(c.max)
+        };
+        (class, distance, c.scale != scale, c.size.abs_diff(size))
+    })
+}
+
+/// A theme followed by its inheritance chain, always ending in hicolor
+pub struct Themes {
+    themes: Vec<Theme>,
+    size: u32,
+    scale: u32,
+    prefer: Prefer,
+}
+
+impl Themes {
+    /// Lookups rank candidates for `size` pixels at the display's integer `scale`
+    pub fn load(name: Option<&str>, size: u32, scale: u32, prefer: Prefer) -> Self {
+        let roots = icon_roots();
+        let mut themes = Vec::new();
+        let mut seen = vec![String::from("hicolor")];
+        // depth first as the spec searches each parent fully before the next
+        let mut stack: Vec<String> = name.into_iter().map(|n| n.to_string()).collect();
+        while let Some(name) = stack.pop() {
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name.clone());
+            if let Some(theme) = Theme::load(&roots, &name) {
+                stack.extend(theme.inherits.iter().rev().cloned());
+                themes.push(theme);
+            } else if themes.is_empty() {
+                eprintln!("Icon theme \"{}\" not found, using hicolor", name)
+            }
+        }
+        themes.extend(Theme::load(&roots, "hicolor"));
+        #[cfg(debug_assertions)]
+        eprintln!(
+            "{} icon directories in {} themes",
+            themes.iter().map(|t| t.dirs.len()).sum::<usize>(),
+            themes.len()
+        );
+        Self {
+            themes,
+            size,
+            scale,
+            prefer,
+        }
+    }
+
+    /// Every icon name in the chain, for checking many names without a lookup each
+    pub fn names(&self) -> HashSet<String> {
+        self.themes.iter().flat_map(Theme::names).collect()
+    }
+
+    /// Themed lookup for icon names, ignoring any image extension.
+    /// The first theme in the chain with the icon wins before any ranking
+    pub fn lookup(&self, name: &str) -> Option<PathBuf> {
+        if Path::new(name).is_absolute() {
+            return None;
+        }
+        let name = strip_extension(name);
+        self.themes
+            .iter()
+            .find_map(|theme| rank(&theme.candidates(name), self.size, self.scale, self.prefer).map(|c| c.path.clone()))
+    }
+
+    /// Themed lookup for the `-symbolic` variant of an icon name
+    pub fn lookup_symbolic(&self, name: &str) -> Option<PathBuf> {
+        self.lookup(&format!("{}-symbolic", strip_extension(name)))
+    }
+}
+
+/// Icons outside of any theme, for when themed lookup fails.
+/// Absolute paths are used as is, otherwise the base directories then pixmaps are tried
+pub fn get_icon_loc(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let name = strip_extension(name);
+    icon_roots()
+        .into_iter()
+        .chain(crate::discovery::data_dirs().into_iter().map(|d| d.join("pixmaps")))
+        .flat_map(|dir| EXTENSIONS.map(|ext| dir.join(format!("{}.{}", name, ext))))
+        .find(|path| path.is_file())
+}
+
+/// Seconds before a name that wasn't found is looked up again,
+/// as there's no cheap way to tell when some theme starts providing it
+const MISS_RETRY: u64 = 24 * 60 * 60;
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Resolved paths from previous launches so a warm start reads no theme at all.
+/// Saved as `key\tsecs\tpath` lines, the path being empty for names that weren't found
+#[derive(Default)]
+pub struct PathCache {
+    old: HashMap<String, (u64, Option<PathBuf>)>,
+    new: Mutex<HashMap<String, (u64, Option<PathBuf>)>>,
+}
+
+impl PathCache {
+    /// Unparseable lines are skipped, so a damaged file only costs lookups
+    pub fn load(file: &Path) -> Self {
+        let old = read_to_string(file)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.rsplitn(3, '\t');
+                let path = fields.next()?;
+                let secs = fields.next()?.parse().ok()?;
+                let path = (!path.is_empty()).then(|| PathBuf::from(path));
+                Some((fields.next()?.to_string(), (secs, path)))
+            })
+            .collect();
+        Self {
+            old,
+            new: Mutex::default(),
+        }
+    }
+
+    /// The path saved for `key` while it's still a file, otherwise the result of `find`
+    pub fn get_or(&self, key: String, find: impl FnOnce() -> Option<PathBuf>) -> Option<PathBuf> {
+        let entry = match self.old.get(&key) {
+            Some((secs, Some(path))) if path.is_file() => (*secs, Some(path.clone())),
+            Some((secs, None)) if now().saturating_sub(*secs) < MISS_RETRY => (*secs, None),
+            _ => (now(), find()),
+        };
+        let path = entry.1.clone();
+        self.new.lock().unwrap().insert(key, entry);
+        path
+    }
+
+    /// Write every entry if any lookup changed one. Entries for other sizes and themes are kept.
+    /// Written beside the target then renamed over it
+    pub fn save(self, file: &Path) -> std::io::Result<()> {
+        let new = self.new.into_inner().unwrap();
+        if new.iter().all(|(key, entry)| self.old.get(key) == Some(entry)) {
+            return Ok(());
+        }
+        let mut entries = self.old;
+        entries.extend(new);
+        let mut data = String::new();
+        for (key, (secs, path)) in entries {
+            let path = match path.as_deref().map(Path::to_str) {
+                Some(Some(path)) => path,
+                // left to be looked up again rather than mangled
+                Some(None) => continue,
+                None => "",
+            };
+            if !(key.contains('\n') || path.contains(['\t', '\n'])) {
+                data += &format!("{}\t{}\t{}\n", key, secs, path)
+            }
+        }
+        let mut temp = file.as_os_str().to_os_string();
+        temp.push(".tmp");
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?
+        }
+        std::fs::write(&temp, data)?;
+        std::fs::rename(&temp, file)
+    }
+}
+
+/// Extension, MIME type, and the naming spec's generic icon for themes without the specific one.
+/// Only common types, as guessing by extension is already rough
+const FILE_TYPES: [(&str, &str, &str); 48] = [
+    ("txt", "text/plain", "text-x-generic"),
+    ("md", "text/markdown", "text-x-generic"),
+    ("log", "text/x-log", "text-x-generic"),
+    ("csv", "text/csv", "x-office-spreadsheet"),
+    ("html", "text/html", "text-html"),
+    ("htm", "text/html", "text-html"),
+    ("css", "text/css", "text-x-generic"),
+    ("c", "text/x-csrc", "text-x-generic"),
+    ("h", "text/x-chdr", "text-x-generic"),
+    ("cpp", "text/x-c++src", "text-x-generic"),
+    ("rs", "text/rust", "text-x-generic"),
+    ("toml", "application/toml", "text-x-generic"),
+    ("json", "application/json", "text-x-generic"),
+    ("xml", "application/xml", "text-x-generic"),
+    ("py", "text/x-python", "text-x-script"),
+    ("sh", "application/x-shellscript", "text-x-script"),
+    ("js", "application/javascript", "text-x-script"),
+    ("pdf", "application/pdf", "x-office-document"),
+    ("odt", "application/vnd.oasis.opendocument.text", "x-office-document"),
+    ("doc", "application/msword", "x-office-document"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "x-office-document",
+    ),
+    (
+        "ods",
+        "application/vnd.oasis.opendocument.spreadsheet",
+        "x-office-spreadsheet",
+    ),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "x-office-spreadsheet",
+    ),
+    (
+        "odp",
+        "application/vnd.oasis.opendocument.presentation",
+        "x-office-presentation",
+    ),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "x-office-presentation",
+    ),
+    ("png", "image/png", "image-x-generic"),
+    ("jpg", "image/jpeg", "image-x-generic"),
+    ("jpeg", "image/jpeg", "image-x-generic"),
+    ("gif", "image/gif", "image-x-generic"),
+    ("webp", "image/webp", "image-x-generic"),
+    ("svg", "image/svg+xml", "image-x-generic"),
+    ("mp3", "audio/mpeg", "audio-x-generic"),
+    ("flac", "audio/flac", "audio-x-generic"),
+    ("ogg", "audio/ogg", "audio-x-generic"),
+    ("opus", "audio/x-opus+ogg", "audio-x-generic"),
+    ("wav", "audio/x-wav", "audio-x-generic"),
+    ("mp4", "video/mp4", "video-x-generic"),
+    ("mkv", "video/x-matroska", "video-x-generic"),
+    ("webm", "video/webm", "video-x-generic"),
+    ("zip", "application/zip", "package-x-generic"),
+    ("tar", "application/x-tar", "package-x-generic"),
+    ("gz", "application/gzip", "package-x-generic"),
+    ("xz", "application/x-xz", "package-x-generic"),
+    ("zst", "application/zstd", "package-x-generic"),
+    ("7z", "application/x-7z-compressed", "package-x-generic"),
+    ("iso", "application/x-cd-image", "media-optical"),
+    ("ttf", "font/ttf", "font-x-generic"),
+    ("otf", "font/otf", "font-x-generic"),
+];
+
+/// Themed icon for an existing file or directory by its extension, such as `application-pdf`,
+/// or the generic icon for its kind if `names` lacks it. None if nothing exists at `path`
+pub fn file_icon(path: &Path, names: &HashSet<String>) -> Option<String> {
+    let meta = path.metadata().ok()?;
+    let (specific, generic) = if meta.is_dir() {
+        ("inode-directory".to_string(), "folder")
+    } else {
+        let ext = path.extension().and_then(OsStr::to_str).map(str::to_ascii_lowercase);
+        match FILE_TYPES.iter().find(|(e, _, _)| Some(*e) == ext.as_deref()) {
+            Some((_, mime, generic)) => (mime.replace('/', "-"), *generic),
+            None if std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o111 != 0 => {
+                ("application-x-executable".to_string(), "application-x-executable")
+            }
+            None => ("text-x-generic".to_string(), "text-x-generic"),
+        }
+    };
+    Some(if names.contains(&specific) {
+        specific
+    } else {
+        generic.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raster(name: &str, size: u32, scale: u32) -> Candidate {
+        Candidate {
+            path: PathBuf::from(name),
+            scalable: false,
+            size: size * scale,
+            min: size * scale,
+            max: size * scale,
+            scale,
+        }
+    }
+
+    fn svg(name: &str) -> Candidate {
+        Candidate {
+            path: PathBuf::from(name),
+            scalable: true,
+            size: 48,
+            min: 8,
+            max: 512,
+            scale: 1,
+        }
+    }
+
+    fn ranked(candidates: &[Candidate], size: u32, scale: u32, prefer: Prefer) -> &str {
+        rank(candidates, size, scale, prefer).unwrap().path.to_str().unwrap()
+    }
+
+    #[test]
+    fn rank_prefer() {
+        let both = [svg("svg"), raster("small", 16, 1), raster("large", 64, 1)];
+        assert_eq!(ranked(&both, 48, 1, Prefer::Auto), "large");
+        assert_eq!(ranked(&both, 128, 1, Prefer::Auto), "svg");
+        assert_eq!(ranked(&both, 48, 1, Prefer::Svg), "svg");
+        assert_eq!(ranked(&both, 128, 1, Prefer::Raster), "large");
+        assert!(rank(&[], 48, 1, Prefer::Auto).is_none());
+    }
+
+    #[test]
+    fn rank_size_and_scale() {
+        let sizes = [raster("32", 32, 1), raster("64", 64, 1), raster("48", 48, 1)];
+        assert_eq!(ranked(&sizes, 48, 1, Prefer::Auto), "48");
+        assert_eq!(ranked(&sizes, 40, 1, Prefer::Auto), "48");
+        // same pixels, the directory meant for the display's scale wins
+        let scaled = [raster("48", 96, 1), raster("48@2", 48, 2)];
+        assert_eq!(ranked(&scaled, 96, 2, Prefer::Auto), "48@2");
+        assert_eq!(ranked(&scaled, 96, 1, Prefer::Auto), "48");
+    }
+
+    /// Theme "test" with app in fixed and scalable directories plus a scalable other
+    fn theme_root(test: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("linch-icons-{}-{}", test, std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        let theme = root.join("test");
+        for dir in ["48x48/apps", "scalable/apps"] {
+            std::fs::create_dir_all(theme.join(dir)).unwrap();
+        }
+        std::fs::write(
+            theme.join("index.theme"),
+            "[Icon Theme]\nDirectories=48x48/apps,scalable/apps\n\n[48x48/apps]\nSize=48\nType=Fixed\n\n\
+             [scalable/apps]\nSize=48\nType=Scalable\nMinSize=8\nMaxSize=512\n",
+        )
+        .unwrap();
+        std::fs::write(theme.join("48x48/apps/app.png"), "").unwrap();
+        std::fs::write(theme.join("scalable/apps/app.svg"), "").unwrap();
+        std::fs::write(theme.join("scalable/apps/other.svg"), "").unwrap();
+        root
+    }
+
+    #[test]
+    fn theme_probes_names() {
+        let root = theme_root("probe");
+        let theme = Theme::load(std::slice::from_ref(&root), "test").unwrap();
+        let mut paths: Vec<_> = theme
+            .candidates("app")
+            .into_iter()
+            .map(|c| (c.path, c.scalable))
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                (root.join("test/48x48/apps/app.png"), false),
+                (root.join("test/scalable/apps/app.svg"), true)
+            ]
+        );
+        assert!(theme.candidates("missing").is_empty());
+        let mut names: Vec<_> = theme.names().collect();
+        names.sort();
+        assert_eq!(names, ["app", "app", "other"]);
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn icon_values() {
+        let root = theme_root("values");
+        let themes = Themes {
+            themes: vec![Theme::load(std::slice::from_ref(&root), "test").unwrap()],
+            size: 48,
+            scale: 1,
+            prefer: Prefer::Svg,
+        };
+        let svg = root.join("test/scalable/apps/app.svg");
+        // bare name
+        assert_eq!(themes.lookup("app"), Some(svg.clone()));
+        // extension is ignored for themed lookup, whatever its case
+        assert_eq!(themes.lookup("app.png"), Some(svg.clone()));
+        assert_eq!(themes.lookup("app.SVG"), Some(svg.clone()));
+        assert_eq!(strip_extension("org.app.Name"), "org.app.Name");
+        // absolute paths skip the theme and are used only if they exist
+        let path = svg.to_str().unwrap();
+        assert_eq!(themes.lookup(path), None);
+        assert_eq!(get_icon_loc(path), Some(svg.clone()));
+        assert_eq!(get_icon_loc(root.join("test/missing.png").to_str().unwrap()), None);
+        std::fs::remove_dir_all(root).ok();
+    }
+}
@@ -0,0 +1,11 @@
+// The parts of linch that work without its window. Building with only the
+// `discovery` or `matching` features leaves out eframe, resvg, and image.
+
+#[cfg(feature = "discovery")]
+pub mod desktop;
+#[cfg(feature = "discovery")]
+pub mod discovery;
+#[cfg(feature = "discovery")]
+pub mod icons;
+#[cfg(feature = "matching")]
+pub mod matching;
@@ -1,346 +1,1013 @@
-use std::ffi::{OsStr, OsString};
-use std::fs::{read_to_string, remove_file, write, File};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{read_to_string, remove_file};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::{collections::HashMap, env, io::Read, num::NonZeroUsize, os::unix::fs::PermissionsExt, path::PathBuf};
+use std::{env, io::Read, io::Write, num::NonZeroUsize};
 
-use colcon::{convert_space, convert_space_chunked, Space};
+use colcon::Space;
 use eframe::egui::style::{ScrollStyle, Selection, Spacing, WidgetVisuals, Widgets};
 use eframe::egui::{
-    CentralPanel, Color32, ColorImage, Context, Frame, Grid, Image, Key, Modifiers, Sense, Stroke, Style, TextEdit,
-    TextureHandle, TextureOptions, ViewportBuilder, ViewportCommand, Visuals, WindowLevel,
+    CentralPanel, Color32, ColorImage, Context, Event, Frame, Grid, Image, ImeEvent, Key, Modifiers, Sense, Stroke,
+    Style, TextEdit, TextureHandle, TextureOptions, ViewportBuilder, ViewportCommand, Visuals, WindowLevel,
 };
-use eframe::epaint::{FontId, Rgba, Rounding, Shadow, Vec2};
+use eframe::epaint::text::{FontData, FontDefinitions, LayoutJob, TextWrapping};
+use eframe::epaint::{FontFamily, FontId, Pos2, Rect, Rgba, Rounding, Shadow, Vec2};
 use eframe::{emath::Align2, App, NativeOptions};
 
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{error::ErrorKind, CommandFactory, FromArgMatches, Parser, Subcommand};
 use lexical_sort::natural_lexical_cmp;
 use regex::Regex;
-use resvg::{tiny_skia, usvg};
-use walkdir::WalkDir;
 
 use rayon::prelude::*;
 
-#[derive(Clone, PartialEq, Eq)]
-struct Item {
-    name: String,
-    file: Option<PathBuf>,
-    exec: Option<String>,
-    path: Option<PathBuf>,
-    icon: Option<String>,
-    hidden: bool,
-}
-
-impl Item {
-    fn from_path(path: PathBuf) -> Result<Self, ()> {
-        let fname = path.file_name().map(|osstr| osstr.to_string_lossy().to_string());
-        fname
-            .map(|name| Self {
-                name,
-                file: Some(path),
-                path: None,
-                exec: None,
-                icon: None,
-                hidden: false,
-            })
-            .ok_or(())
+use linch::discovery::{data_dirs, get_applications, get_binaries, Item, Name};
+use linch::matching::{self, search_matches};
+use linch::{desktop, icons};
+
+mod bench;
+mod calc;
+mod color;
+mod emoji;
+mod monitor;
+mod protocol;
+mod raster;
+mod storage;
+mod theme;
+
+fn parse_fraction(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|f| (0.0..=1.0).contains(f))
+        .ok_or_else(|| String::from("\"") + s + "\" is not a fraction from 0.0 to 1.0")
+}
+
+/// Window size in pixels or as a fraction of the monitor
+#[derive(Clone, Copy)]
+enum Length {
+    Pixels(f32),
+    Fraction(f32),
+}
+
+impl Length {
+    /// Pixels, or `default` until the monitor size is known
+    fn or_pixels(self, default: f32) -> f32 {
+        match self {
+            Self::Pixels(p) => p,
+            Self::Fraction(_) => default,
+        }
     }
-    fn from_desktop(path: PathBuf) -> Result<Self, ()> {
-        // {{{
-        if path.extension() == Some(OsString::from("desktop").as_os_str()) {
-            if let Ok(data) = read_to_string(&path) {
-                let mut start = false;
-                let mut hm = HashMap::new();
-                for line in data.lines() {
-                    if line.trim() == "[Desktop Entry]" {
-                        start = true;
-                    } else if line.trim().starts_with("[") {
-                        break;
-                    } else if start {
-                        if let Some((a, b)) = line.split_once("=") {
-                            hm.insert(a.trim().to_string(), b.trim_start().to_string());
-                        }
-                    }
-                }
-                if let Some(name) = hm.get(&String::from("Name")) {
-                    Ok(Self {
-                        name: name.to_string(),
-                        file: Some(path),
-                        exec: hm.get(&String::from("Exec")).cloned(),
-                        icon: hm.get(&String::from("Icon")).cloned(),
-                        path: hm.get(&String::from("Path")).cloned().map(|s| PathBuf::from(s)),
-                        hidden: hm
-                            .get(&String::from("Hidden"))
-                            .map(|s| s.parse::<bool>().ok())
-                            .flatten()
-                            .unwrap_or(false)
-                            | hm.get(&String::from("NoDisplay"))
-                                .map(|s| s.parse::<bool>().ok())
-                                .flatten()
-                                .unwrap_or(false),
-                    })
-                } else {
-                    Err(())
-                }
-            } else {
-                Err(())
-            }
-        } else {
-            Err(())
+
+    fn fraction(self) -> Option<f32> {
+        match self {
+            Self::Pixels(_) => None,
+            Self::Fraction(f) => Some(f),
         }
-    } // }}}
-}
+    }
 
-impl AsRef<str> for Item {
-    fn as_ref(&self) -> &str {
-        &self.name
+    fn scaled(self, k: f32) -> Self {
+        match self {
+            Self::Pixels(p) => Self::Pixels(p * k),
+            Self::Fraction(f) => Self::Fraction(f * k),
+        }
     }
 }
 
-impl std::fmt::Display for Item {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.as_ref())
+fn parse_length(s: &str) -> Result<Length, String> {
+    match s.strip_suffix('%') {
+        Some(percent) => percent
+            .parse::<f32>()
+            .ok()
+            .filter(|p| *p > 0.0 && *p <= 100.0)
+            .map(|p| Length::Fraction(p / 100.0))
+            .ok_or_else(|| String::from("\"") + s + "\" is not a percentage from 0% to 100%"),
+        None => s.parse::<f32>().map(Length::Pixels).map_err(|e| e.to_string()),
     }
 }
 
+fn parse_position(s: &str) -> Result<[f32; 2], String> {
+    s.split_once(',')
+        .and_then(|(x, y)| Some([x.trim().parse().ok()?, y.trim().parse().ok()?]))
+        .ok_or_else(|| String::from("\"") + s + "\" is not X,Y")
+}
+
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .filter(|(k, _)| !k.is_empty())
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| String::from("\"") + s + "\" is not VAR=VALUE")
+}
+
 fn parse_color(s: &str) -> Result<Color32, String> {
     colcon::str2space::<f32, 3>(s, Space::LRGB)
         .map(|rgb| Color32::from(Rgba::from_rgb(rgb[0], rgb[1], rgb[2])))
         .ok_or_else(|| String::from("Could not parse \"") + s + "\" as a color.")
 }
 
-// Reference:
-// https://specifications.freedesktop.org/basedir-spec/basedir-spec-latest.html
-// https://specifications.freedesktop.org/desktop-entry-spec/desktop-entry-spec-latest.html
+/// `[foreground, background, accent]`, or all three derived from the accent
+fn palette(fg: Color32, bg: Color32, acc: Color32, from_accent: bool) -> [Color32; 5] {
+    if from_accent {
+        let acc = Rgba::from(acc);
+        color::palette_from_accent([acc[0], acc[1], acc[2]])
+            .map(|rgb| Color32::from(Rgba::from_rgb(rgb[0], rgb[1], rgb[2])))
+    } else {
+        [fg, bg, acc, acc, acc.gamma_multiply(0.5)]
+    }
+}
+
+/// Apply --monochrome to a rendered icon, or with --symbolic-fallback tint symbolic ones
+/// to the text color, or the accent if monochrome
+fn recolor(path: &Path, ci: &mut ColorImage, monochrome: bool, symbolic: bool, fg: Color32, acc: Color32) {
+    let symbolic = symbolic && path.file_stem().is_some_and(|s| s.as_bytes().ends_with(b"-symbolic"));
+    if symbolic {
+        color::tint_image(if monochrome { acc } else { fg }, ci)
+    } else if monochrome {
+        color::monochromatize_image(acc, ci)
+    }
+}
+
+/// Textures and their source files by icon name. Icons that failed to decode get neither,
+/// so they're drawn as the letter placeholder like icons that weren't found
+fn load_textures(
+    ctx: &Context,
+    color_images: HashMap<String, (PathBuf, Option<ColorImage>)>,
+) -> (HashMap<String, TextureHandle>, HashMap<String, PathBuf>) {
+    let mut images = HashMap::new();
+    let mut icon_sources = HashMap::new();
+    for (k, (path, v)) in color_images.into_iter() {
+        let Some(v) = v else { continue };
+        let th = ctx.load_texture(&k, v, TextureOptions::default());
+        images.insert(k.clone(), th);
+        icon_sources.insert(k, path);
+    }
+    (images, icon_sources)
+}
+
+/// Square icon raster size in physical pixels, matching the slot inside a row's margins unless overridden.
+/// `scale` includes the monitor's own pixels per point so HiDPI icons are drawn 1:1
+fn icon_px(icon_size: Option<u32>, size: [f32; 2], scale: f32, rows: usize) -> u32 {
+    let side = icon_size.map_or((size[1] - 10.0) / (rows + 1) as f32 - 4.0, |s| s as f32);
+    (side * scale).round().max(1.0) as u32
+}
+
+/// Window height before scaling whose rows fit --icon-size icons, the inverse of `icon_px`
+fn icon_height(icon_size: Option<u32>, rows: usize) -> f32 {
+    icon_size.map_or(0.0, |s| (s as f32 + 4.0) * (rows + 1) as f32 + 10.0)
+}
+
+/// Substitute `{count}` matches, `{total}` items, and `{mode}` in the prompt.
+/// `{{` and `}}` are literal braces and unknown placeholders are left as written
+fn format_prompt(prompt: &str, count: usize, total: usize, mode: &str) -> String {
+    let mut result = String::with_capacity(prompt.len());
+    let mut rest = prompt;
+    while let Some(n) = rest.find(['{', '}']) {
+        result.push_str(&rest[..n]);
+        rest = &rest[n..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            result.push_str(&rest[..1]);
+            rest = &rest[2..];
+        } else if let Some((name, tail)) = rest[1..].split_once('}').filter(|_| rest.starts_with('{')) {
+            match name {
+                "count" => result.push_str(&count.to_string()),
+                "total" => result.push_str(&total.to_string()),
+                "mode" => result.push_str(mode),
+                _ => {
+                    result.push('{');
+                    result.push_str(name);
+                    result.push('}');
+                }
+            }
+            rest = tail;
+        } else {
+            result.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// First executable file named `name` in PATH
+fn which(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| {
+            path.metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0b1 == 0b1)
+        })
+}
+
+/// file:// URI for an absolute path, percent encoding all but unreserved characters and `/`
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(b) {
+            uri.push(*b as char)
+        } else {
+            uri.push_str(&format!("%{:02X}", b))
+        }
+    }
+    uri
+}
+
+/// Desktop file ID without .desktop, the bus name for D-Bus activation and what gtk-launch takes.
+/// Entries that weren't discovered fall back to their file name
+fn launch_id(item: &Item) -> String {
+    item.id
+        .as_deref()
+        .map(|id| id.strip_suffix(".desktop").unwrap_or(id).to_string())
+        .or_else(|| Some(item.file.as_ref()?.file_stem()?.to_string_lossy().to_string()))
+        .unwrap_or_default()
+}
+
+/// gdbus call activating `id` through org.freedesktop.Application, one of its actions, or opening `files` with it
+fn dbus_activation(id: &str, action: Option<&str>, files: &[PathBuf]) -> std::process::Command {
+    let mut command = std::process::Command::new("gdbus");
+    command
+        .args(["call", "--session", "--dest", id, "--object-path"])
+        .arg(String::from("/") + &id.replace('.', "/").replace('-', "_"));
+    // args are GVariant text, so strings need quoting
+    match action {
+        Some(action) => command
+            .args(["--method", "org.freedesktop.Application.ActivateAction"])
+            .arg(format!("'{}'", action))
+            .args(["[]", "{}"]),
+        // percent encoding leaves no quotes in the URIs
+        None if !files.is_empty() => command
+            .args(["--method", "org.freedesktop.Application.Open"])
+            .arg(format!(
+                "[{}]",
+                files
+                    .iter()
+                    .map(|f| format!("'{}'", file_uri(f)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .arg("{}"),
+        None => command.args(["--method", "org.freedesktop.Application.Activate", "{}"]),
+    };
+    command.stdout(std::process::Stdio::null());
+    command
+}
+
+/// $TERMINAL or the first common terminal emulator in PATH
+fn terminal_emulator() -> Option<OsString> {
+    env::var_os("TERMINAL").filter(|t| !t.is_empty()).or_else(|| {
+        ["x-terminal-emulator", "foot", "alacritty", "xterm"]
+            .into_iter()
+            .find_map(|t| which(t).map(PathBuf::into_os_string))
+    })
+}
 
-fn get_binaries() -> Vec<Item> {
+/// Split an Exec value into arguments following the spec's quoting rules.
+/// String escapes apply first, so a literal backslash inside quotes is `\\\\` in the file.
+/// Each argument is paired with whether any part of it was quoted
+fn tokenize_exec(exec: &str) -> Vec<(String, bool)> {
     // {{{
-    let mut binaries = Vec::new();
-    if let Ok(paths) = env::var("PATH") {
-        for directory in paths.split(':') {
-            for entry in WalkDir::new(directory).follow_links(true) {
-                if let Ok(entry) = entry {
-                    if let Ok(meta) = entry.metadata() {
-                        let bit = 0b1;
-                        if !meta.is_dir() && meta.permissions().mode() & bit == bit {
-                            let path = entry.into_path();
-                            if let Ok(item) = Item::from_path(path) {
-                                binaries.push(item);
-                            }
-                        }
-                    }
+    let exec = desktop::unescape_value(exec);
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let (mut started, mut quoted, mut in_quotes) = (false, false, false);
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+                started = true;
+            }
+            '\\' if in_quotes => match chars.next() {
+                Some(e @ ('"' | '`' | '$' | '\\')) => arg.push(e),
+                Some(e) => {
+                    arg.push('\\');
+                    arg.push(e)
+                }
+                None => arg.push('\\'),
+            },
+            ' ' | '\t' | '\n' if !in_quotes => {
+                if started {
+                    args.push((std::mem::take(&mut arg), quoted));
                 }
+                (started, quoted) = (false, false);
+            }
+            c => {
+                arg.push(c);
+                started = true;
             }
         }
     }
-    binaries
+    if started {
+        args.push((arg, quoted))
+    }
+    args
 } // }}}
 
-fn get_applications(include_hidden: bool) -> Vec<Item> {
+/// Split typed arguments shell style, without any expansion
+fn split_arguments(text: &str) -> Vec<String> {
     // {{{
-    let mut paths = Vec::<PathBuf>::new();
-    // add them in backwards because the desktop entry spec
-    // states it should return the first found
-    paths.extend(
-        env::var("XDG_DATA_DIRS")
-            .unwrap_or(String::from("/usr/local/share/:/usr/share/"))
-            .split(':')
-            .rev()
-            .map(|s| s.into()),
-    );
-    paths.push(
-        env::var_os("XDG_DATA_HOME")
-            .unwrap_or(OsString::from(env::var("HOME").unwrap() + "/.local/share"))
-            .into(),
-    );
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let (mut started, mut quote) = (false, None);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', None) | ('"', None) => {
+                quote = Some(c);
+                started = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            ('\\', None | Some('"')) => {
+                arg.extend(chars.next());
+                started = true;
+            }
+            (c, None) if c.is_whitespace() => {
+                if started {
+                    args.push(std::mem::take(&mut arg));
+                }
+                started = false;
+            }
+            (c, _) => {
+                arg.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        args.push(arg)
+    }
+    args
+} // }}}
 
-    let mut result = Vec::new();
+/// Variables offloading to the discrete GPU. PRIME for Mesa, plus the NVIDIA ones when its driver is loaded
+fn dgpu_env() -> Vec<(String, String)> {
+    let mut vars = vec![(String::from("DRI_PRIME"), String::from("1"))];
+    if Path::new("/proc/driver/nvidia").exists() {
+        vars.push((String::from("__NV_PRIME_RENDER_OFFLOAD"), String::from("1")));
+        vars.push((String::from("__GLX_VENDOR_LIBRARY_NAME"), String::from("nvidia")));
+    }
+    vars
+}
 
-    for mut path in paths {
-        path.push("applications");
-        for entry in WalkDir::new(path).follow_links(true) {
-            if let Ok(entry) = entry {
-                if let Ok(item) = Item::from_desktop(entry.into_path()) {
-                    if include_hidden | !item.hidden {
-                        result.push(item);
+/// Split a desktop entry Exec into arguments with field codes expanded.
+/// `%f` and `%u` become the first of `files` and `%F` and `%U` all of them, or are dropped without any.
+/// The `%u` and `%U` forms are file:// URIs.
+/// `%i` becomes `--icon <Icon>`, `%c` the name, `%k` the desktop file, and `%%` a literal `%`
+fn expand_exec(exec: &str, item: &Item, files: &[PathBuf]) -> Vec<String> {
+    // {{{
+    let mut result = Vec::new();
+    for (arg, quoted) in tokenize_exec(exec) {
+        // field codes aren't valid inside quotes
+        if quoted {
+            result.push(arg);
+            continue;
+        }
+        if arg == "%i" {
+            if let Some(icon) = item.icon.as_ref() {
+                result.push(String::from("--icon"));
+                result.push(icon.clone());
+            }
+            continue;
+        }
+        if arg == "%F" {
+            result.extend(files.iter().map(|f| f.to_string_lossy().to_string()));
+            continue;
+        }
+        if arg == "%U" {
+            result.extend(files.iter().map(|f| file_uri(f)));
+            continue;
+        }
+        let mut expanded = String::new();
+        let mut chars = arg.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => expanded.push('%'),
+                Some('c') => expanded.push_str(&item.name),
+                Some('k') => {
+                    if let Some(file) = item.file.as_ref() {
+                        expanded.push_str(&file.to_string_lossy())
                     }
                 }
+                Some('f') => {
+                    if let Some(file) = files.first() {
+                        expanded.push_str(&file.to_string_lossy())
+                    }
+                }
+                Some('u') => {
+                    if let Some(file) = files.first() {
+                        expanded.push_str(&file_uri(file))
+                    }
+                }
+                // %F %U inside a word, the deprecated codes, and anything invalid are removed
+                _ => (),
             }
         }
+        // an argument made only of removed codes disappears entirely
+        if !expanded.is_empty() {
+            result.push(expanded)
+        }
     }
-
     result
 } // }}}
 
-fn get_icon_loc(name: &str) -> Option<PathBuf> {
-    // {{{
-    // on my system covers every app that doesn't have a stupid location
-    for f in [
-        name.to_string(),
-        // Prefer Papirus SVGs
-        format!("/usr/share/icons/Papirus/64x64/apps/{}.svg", name),
-        format!("/usr/share/icons/hicolor/scalable/apps/{}.svg", name),
-        // HiColor PNGs
-        format!("/usr/share/icons/hicolor/64x64/apps/{}.png", name),
-        format!("/usr/share/icons/hicolor/128x128/apps/{}.png", name),
-        format!("/usr/share/icons/hicolor/256x256/apps/{}.png", name),
-        // Check other locations
-        format!("/usr/share/icons/Papirus/64x64/devices/{}.svg", name),
-    ] {
-        let buf = PathBuf::from(f);
-        if buf.is_file() {
-            return Some(buf);
-        }
-    }
-    // fall back to scanning. Don't like this, kind of want to remove but idk how other
-    // distros'/themes' layouts may differ
-    let osname = Some(OsStr::new(name));
-    let png = Some(OsStr::new("png"));
-    let svg = Some(OsStr::new("svg"));
-    // No Papirus scannign since its layout is super standardized and there's like a million files
-    for entry in WalkDir::new("/usr/share/icons/hicolor")
+/// Exec reduced to what identifies the command: field codes and `env VAR=value` prefixes are dropped
+/// and the program is compared by file name. With `ignore_args` only the program remains
+fn normalized_exec(exec: &str, ignore_args: bool) -> Option<Vec<String>> {
+    let mut args = tokenize_exec(exec)
         .into_iter()
-        .chain(WalkDir::new("/usr/share/icons/Adwaita"))
-        // scan all other themes as last resort
-        .chain(WalkDir::new("/usr/share/icons").into_iter().filter_entry(|e| {
-            e.file_name() != "Papirus"
-                        && e.file_name() != "hicolor"
-                        && e.file_name() != "Adwaita"
-                        // also skip symbolic icons. I'm not gonna support them
-                        && e.file_name() != "symbolic"
-        }))
-        .filter_map(|e| e.ok())
-        .filter(|e| e.path().file_stem() == osname)
-        .filter(|e| e.path().extension() == png || e.path().extension() == svg)
-    {
-        // println!("### FOUND {} AT {}", name, entry.path().display());
-        return Some(entry.path().to_owned());
+        .filter(|(arg, quoted)| *quoted || !(arg.len() == 2 && arg.starts_with('%') && arg != "%%"))
+        .map(|(arg, _)| arg)
+        .skip_while(|arg| arg == "env" || arg.contains('='));
+    let program = args.next()?;
+    let program = program.rsplit('/').next().unwrap_or(&program).to_string();
+    Some(std::iter::once(program).chain(args.filter(|_| !ignore_args)).collect())
+}
+
+/// Collapse entries sharing a normalized Exec into the shortest named shown one,
+/// keeping the rest reachable as its actions
+fn merge_by_exec(items: Vec<Item>, ignore_args: bool) -> Vec<Item> {
+    // {{{
+    let mut groups = HashMap::<Vec<String>, Vec<Item>>::new();
+    let mut result = Vec::new();
+    for item in items {
+        // links and D-Bus only entries have nothing to compare
+        match item.exec.as_deref().and_then(|e| normalized_exec(e, ignore_args)) {
+            Some(key) => groups.entry(key).or_default().push(item),
+            None => result.push(item),
+        }
+    }
+    for mut group in groups.into_values() {
+        group.sort_by(|a, b| (a.no_display, a.name.len(), &a.name).cmp(&(b.no_display, b.name.len(), &b.name)));
+        let mut group = group.into_iter();
+        let mut preferred = group.next().unwrap();
+        preferred.actions.extend(group);
+        result.push(preferred);
     }
-    // println!("### COULD NOT FIND {}", name);
-    None
+    result
 } // }}}
 
-fn monochromatize(mut reference: [f32; 3], target: &mut [[f32; 4]], target_space: Space) {
-    // {{{
-    convert_space(Space::LRGB, Space::JZCZHZ, &mut reference);
-    convert_space_chunked(target_space, Space::JZCZHZ, target);
+/// XDG_SESSION_TYPE, else guessed from which display variables are set
+fn session_type(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    var("XDG_SESSION_TYPE")
+        .filter(|s| !s.is_empty())
+        .or_else(|| var("WAYLAND_DISPLAY").map(|_| String::from("wayland")))
+        .or_else(|| var("DISPLAY").map(|_| String::from("x11")))
+}
 
-    let [lmax, cmax, _] = Space::JZCZHZ.srgb_quants()[100];
-    let [lmin, cmin, _] = Space::JZCZHZ.srgb_quants()[0];
+/// The process environment as the lookup the detection functions take
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
 
-    target.iter_mut().for_each(|chunk| {
-        let l = (chunk[0] - lmin) / lmax + lmin / lmax;
-        let c = (chunk[1] - cmin) / cmax + cmin / cmax;
-        let h = chunk[2];
+const SCALE_VARS: [&str; 2] = ["GDK_DPI_SCALE", "GDK_SCALE"];
+
+/// Everything `--version-json` reports, from the same lookups the modes use
+fn version_report() -> protocol::VersionReport {
+    let path = |p: Option<PathBuf>| p.map(|p| p.to_string_lossy().to_string());
+    protocol::VersionReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features: [
+            ("gui", cfg!(feature = "gui")),
+            ("discovery", cfg!(feature = "discovery")),
+            ("matching", cfg!(feature = "matching")),
+        ]
+        .into_iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| name.to_string())
+        .collect(),
+        renderer: NativeOptions::default().renderer.to_string(),
+        session_type: session_type(env_var),
+        desktop: env::var("XDG_CURRENT_DESKTOP").ok().filter(|s| !s.is_empty()),
+        scale_sources: SCALE_VARS
+            .into_iter()
+            .filter_map(|v| env_var(v).map(|val| format!("{}={}", v, val)))
+            .collect(),
+        scale: scale_factor(env_var),
+        icon_theme: icons::gtk_theme(),
+        data_dirs: data_dirs()
+            .into_iter()
+            .map(|d| d.to_string_lossy().to_string())
+            .collect(),
+        config_dir: path(config_dir()),
+        state_dir: path(storage::state_dir()),
+        cache_dir: path(storage::cache_dir()),
+    }
+}
 
-        // set hue
-        chunk[2] = reference[2];
+/// The first usable of the GDK scale variables, else 1
+fn scale_factor(var: impl Fn(&str) -> Option<String>) -> f32 {
+    for name in SCALE_VARS {
+        if let Some(val) = var(name) {
+            match val.trim().parse::<f32>() {
+                Ok(scale) if scale.is_finite() && scale > 0.0 => return scale,
+                _ => eprintln!("Ignoring bad {} value {:?}", name, val),
+            }
+        }
+    }
+    1.0
+}
 
-        // adjust chroma up to 50% based on proximity to middle gray
-        chunk[1] = c * 0.5 + (0.5 - (l - 0.5).abs());
+/// Contents of a font file, or of the fontconfig match for a family name
+fn load_font(font: &str) -> Result<Vec<u8>, String> {
+    let path = if Path::new(font).is_file() {
+        PathBuf::from(font)
+    } else {
+        let output = std::process::Command::new("fc-match")
+            .args(["--format=%{file}", font])
+            .output()
+            .map_err(|e| format!("{} is not a file and fc-match failed\n{}", font, e))?;
+        PathBuf::from(OsString::from_vec(output.stdout))
+    };
+    std::fs::read(&path).map_err(|e| format!("Could not read font {}\n{}", path.display(), e))
+}
+
+/// Where --remember-position left the window last time
+fn position_get() -> Option<Pos2> {
+    let data = read_to_string(storage::cache_file("position")?).ok()?;
+    let pos = parse_position(data.trim()).ok()?;
+    Some(Pos2::from(pos))
+}
 
-        // uses a reverse HK delta to exacurbate dark and light hues against the reference
-        let tar_delta = colcon::hk_high2023(&[100.0, 100.0, h]);
-        let ref_delta = colcon::hk_high2023(&[100.0, 100.0, reference[2]]);
-        chunk[0] = l + (ref_delta - tar_delta) / 100.0 / 2.0;
+fn position_set(pos: Pos2) {
+    let Some(file) = storage::cache_file("position") else {
+        return;
+    };
+    let result = file
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&file, format!("{},{}", pos.x, pos.y)));
+    if let Err(e) = result {
+        eprintln!("Could not save window position to {}\n{}", file.display(), e)
+    }
+}
 
-        chunk[0] = (chunk[0] + lmin / lmax) * lmax;
-        chunk[1] = (chunk[1] + lmin / lmax) * lmax;
+/// Put `text` on the clipboard from a detached copy of linch,
+/// as X11 and Wayland clipboards are served by their owner and would be lost once this one exits
+fn clipboard_set(text: &str) {
+    use std::os::unix::process::CommandExt;
+    let child = env::current_exe().and_then(|exe| {
+        std::process::Command::new(exe)
+            .arg("serve-clipboard")
+            .stdin(std::process::Stdio::piped())
+            // an open stdout would keep `$(linch ...)` waiting on it
+            .stdout(std::process::Stdio::null())
+            // so closing a launching script or terminal doesn't take it down
+            .process_group(0)
+            .spawn()
     });
+    let result = child.and_then(|mut child| child.stdin.take().unwrap().write_all(text.as_bytes()));
+    if let Err(e) = result {
+        eprintln!("Could not copy to the clipboard\n{}", e)
+    }
+}
 
-    convert_space_chunked(Space::JZCZHZ, target_space, target);
-} // }}}
+/// Own the clipboard until another program takes it over
+fn clipboard_serve() {
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text).ok();
+    let result = arboard::Clipboard::new().and_then(|mut clipboard| {
+        use arboard::SetExtLinux;
+        clipboard.set().wait().text(text)
+    });
+    if let Err(e) = result {
+        eprintln!("Could not copy to the clipboard\n{}", e)
+    }
+}
 
-fn scale_factor() -> f32 {
-    if let Ok(val) = env::var("GDK_DPI_SCALE") {
-        val.parse::<f32>().expect("Bad GDK_DPI_SCALE value")
-    } else if let Ok(val) = env::var("GDK_SCALE") {
-        val.parse::<f32>().expect("Bad GDK_SCALE value")
+fn config_dir() -> Option<PathBuf> {
+    if let Ok(xdg_config) = env::var("XDG_CONFIG_HOME") {
+        Some(PathBuf::from(xdg_config + "/linch"))
     } else {
-        1.0
+        env::var("HOME").ok().map(|home| PathBuf::from(home + "/.config/linch"))
+    }
+}
+
+// ### Alias FNS {{{
+
+/// Extra search keywords and display renames, keyed by original item name
+#[derive(Default)]
+struct Aliases {
+    keywords: HashMap<String, Vec<String>>,
+    renames: HashMap<String, String>,
+}
+
+/// `keyword = name` adds a search keyword, `name -> Display Name` renames.
+/// Later renames of the same name override earlier ones.
+fn aliases_parse(data: &str) -> Aliases {
+    let mut aliases = Aliases::default();
+    for line in data.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((name, display)) = line.split_once("->") {
+            let (name, display) = (name.trim(), display.trim());
+            if !name.is_empty() && !display.is_empty() {
+                if let Some(old) = aliases.renames.insert(name.to_string(), display.to_string()) {
+                    eprintln!("Alias rename \"{} -> {}\" overridden by \"{}\"", name, old, display)
+                }
+            }
+        } else if let Some((keyword, name)) = line.split_once('=') {
+            let (keyword, name) = (keyword.trim(), name.trim());
+            if !keyword.is_empty() && !name.is_empty() {
+                let keywords = aliases.keywords.entry(name.to_string()).or_default();
+                if !keywords.iter().any(|k| k == keyword) {
+                    keywords.push(keyword.to_string())
+                }
+            }
+        } else {
+            eprintln!("Could not parse alias line \"{}\"", line)
+        }
     }
+    aliases
+}
+
+fn aliases_get() -> Aliases {
+    config_dir()
+        .and_then(|mut dir| {
+            dir.push("aliases");
+            read_to_string(dir).ok()
+        })
+        .map(|data| aliases_parse(&data))
+        .unwrap_or_default()
 }
 
+impl Aliases {
+    fn apply(&self, items: &mut [Item]) {
+        for item in items.iter_mut() {
+            if let Some(keywords) = self.keywords.get(&*item.name) {
+                item.keywords.extend(keywords.iter().cloned())
+            }
+            if let Some(display) = self.renames.get(&*item.name) {
+                item.name = display.as_str().into()
+            }
+        }
+    }
+}
+
+// ### Alias FNS }}}
+
+// ### Binlist FNS {{{
+
+/// Binary names one per line, skipping blanks, `#` comments, and repeats
+fn binlist_parse(data: &str) -> Vec<String> {
+    let mut names = Vec::<String>::new();
+    for line in data.lines().map(|l| l.trim()) {
+        if !line.is_empty() && !line.starts_with('#') && !names.iter().any(|n| n == line) {
+            names.push(line.to_string())
+        }
+    }
+    names
+}
+
+/// The `cache_dir` config file, a path used like --cache-dir when the flag isn't given
+fn cache_dir_get() -> Option<PathBuf> {
+    let data = read_to_string(config_dir()?.join("cache_dir")).ok()?;
+    let dir = data.trim();
+    (!dir.is_empty()).then(|| PathBuf::from(dir))
+}
+
+fn binlist_get() -> Vec<String> {
+    config_dir()
+        .and_then(|mut dir| {
+            dir.push("binlist");
+            read_to_string(dir).ok()
+        })
+        .map(|data| binlist_parse(&data))
+        .unwrap_or_default()
+}
+
+/// Keep only listed binaries in list order, the first PATH match winning.
+/// Missing binaries become dimmed placeholders when `missing` is set
+fn binlist_apply(list: &[String], items: Vec<Item>, missing: bool) -> Vec<Item> {
+    let mut found = HashMap::<String, Item>::new();
+    for item in items {
+        found.entry(item.name.to_string()).or_insert(item);
+    }
+    list.iter()
+        .filter_map(|name| match found.remove(name) {
+            Some(item) => Some(item),
+            None if missing => Some(Item {
+                name: name.as_str().into(),
+                no_display: true,
+                ..Default::default()
+            }),
+            None => None,
+        })
+        .collect()
+}
+
+// ### Binlist FNS }}}
+
 // ### Cache FNS {{{
 
-fn cache_file(name: &str) -> PathBuf {
-    assert!(!name.is_empty());
-    let fname = String::from("/linch_") + name;
-    if let Ok(xdg_cache) = env::var("XDG_CACHE_HOME") {
-        PathBuf::from(xdg_cache + &fname)
-    } else if let Ok(home) = env::var("HOME") {
-        PathBuf::from(home + "/.cache" + &fname)
-    } else {
-        panic!("Could not find cache directory.\nOne of the following must environment variables be set\nXDG_CACHE_HOME\nHOME")
+/// One cache line. Pinned entries sort above every count
+#[derive(Clone)]
+struct CacheEntry {
+    count: usize,
+    pinned: bool,
+    /// Unix time of the last launch. Lines from older versions have none,
+    /// so they're dated to when the file was last written
+    last: Option<u64>,
+    name: String,
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl CacheEntry {
+    /// Count halved every `halflife` days since the last launch, or just the count if that's 0
+    fn score(&self, now: u64, halflife: f32) -> f64 {
+        match self.last {
+            Some(last) if halflife > 0.0 => {
+                let days = now.saturating_sub(last) as f64 / 86400.0;
+                self.count as f64 * 0.5f64.powf(days / halflife as f64)
+            }
+            _ => self.count as f64,
+        }
+    }
+
+    fn badge(&self) -> String {
+        if self.pinned {
+            format!("{} pinned", self.count)
+        } else {
+            self.count.to_string()
+        }
     }
 }
 
-fn cache_get(name: &str) -> Vec<(usize, String)> {
+fn cache_get(name: &str) -> Vec<CacheEntry> {
+    let Some(file) = storage::counts_file(name) else {
+        return Vec::new();
+    };
+    let written = file
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    cache_parse(&read_to_string(file).unwrap_or_default(), written)
+}
+
+/// Lines without a time get `written` instead, so they decay from then on
+fn cache_parse(data: &str, written: Option<u64>) -> Vec<CacheEntry> {
     let mut result = Vec::new();
-    if let Ok(data) = read_to_string(cache_file(name)) {
-        let re = Regex::new(r"^(\d+) +(.+)$").unwrap();
-        for line in data.lines() {
-            if let Some(captures) = re.captures(line.trim()) {
-                result.push((captures[1].parse::<usize>().unwrap(), captures[2].to_string()))
-            }
+    // COUNT[*][@UNIXTIME] NAME
+    let re = Regex::new(r"^(\d+)(\*?)(?:@(\d+))? +(.+)$").unwrap();
+    for line in data.lines() {
+        if let Some(captures) = re.captures(line.trim()) {
+            result.push(CacheEntry {
+                // only fails past usize::MAX
+                count: captures[1].parse::<usize>().unwrap_or(usize::MAX),
+                pinned: !captures[2].is_empty(),
+                last: captures.get(3).and_then(|m| m.as_str().parse().ok()).or(written),
+                name: captures[4].to_string(),
+            })
         }
     }
-    result.sort_by(|a, b| a.0.cmp(&b.0).reverse().then(a.1.cmp(&b.1)));
+    result.sort_by(|a, b| {
+        (a.pinned, a.count)
+            .cmp(&(b.pinned, b.count))
+            .reverse()
+            .then(a.name.cmp(&b.name))
+    });
     result
 }
 
-fn cache_set(name: &str, lines: Vec<(usize, String)>) {
-    write(
-        cache_file(name),
-        lines
-            .into_iter()
-            .map(|(n, s)| format!("{} {}", n, s))
-            .fold(String::new(), |a, b| a + &b + "\n"),
-    )
-    .unwrap();
+/// False with a warning if the cache exists but belongs to another user, or has nowhere to go
+fn cache_usable(name: &str) -> bool {
+    match storage::counts_file(name) {
+        Some(file) => storage::usable(&file),
+        None => {
+            eprintln!("Could not find a state directory, launches won't be counted.\nSet XDG_STATE_HOME or HOME");
+            false
+        }
+    }
 }
 
-fn cache_apply(name: &str, items: &mut [Item]) {
-    let map: HashMap<String, usize> = HashMap::from_iter(cache_get(name).into_iter().map(|(n, s)| (s, n)));
-    items.sort_by(|a, b| {
-        map.get(&a.name.clone())
-            .unwrap_or(&0)
-            .cmp(map.get(&b.name.clone()).unwrap_or(&0))
-            .reverse()
-            .then(natural_lexical_cmp(a.as_ref(), b.as_ref()))
+/// Written to a temporary file and renamed over the cache so readers never see a partial write
+fn cache_set(name: &str, lines: Vec<CacheEntry>) {
+    let Some(file) = storage::counts_file(name) else {
+        return;
+    };
+    let mut temp = file.clone().into_os_string();
+    temp.push(".tmp");
+    // private as the names reveal usage patterns
+    let result = file
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| storage::create_private(Path::new(&temp)))
+        .and_then(|mut f| {
+            f.write_all(
+                lines
+                    .into_iter()
+                    .map(|e| {
+                        format!(
+                            "{}{}{} {}",
+                            e.count,
+                            if e.pinned { "*" } else { "" },
+                            e.last.map(|t| format!("@{}", t)).unwrap_or_default(),
+                            e.name
+                        )
+                    })
+                    .fold(String::new(), |a, b| a + &b + "\n")
+                    .as_bytes(),
+            )
+        })
+        .and_then(|_| std::fs::rename(&temp, &file));
+    // the choice still goes through, only its count is lost
+    if let Err(e) = result {
+        eprintln!("Could not write cache {}\n{}", file.display(), e)
+    }
+}
+
+/// Sort pinned first then by decayed cache count, then naturally if `lexical` or else keeping the existing order
+fn cache_apply(name: &str, items: &mut [Item], lexical: bool, halflife: f32) {
+    cache_sort(cache_get(name), items, lexical, halflife)
+}
+
+fn cache_sort(entries: Vec<CacheEntry>, items: &mut [Item], lexical: bool, halflife: f32) {
+    let now = unix_now();
+    let map: HashMap<String, (bool, f64)> = HashMap::from_iter(
+        entries
+            .into_iter()
+            .map(|e| (e.pinned, e.score(now, halflife), e.name))
+            .map(|(pinned, score, name)| (name, (pinned, score))),
+    );
+    // looked up once per item rather than twice per comparison
+    let mut keyed: Vec<((bool, f64), Item)> = items
+        .iter_mut()
+        .map(|i| (map.get(&*i.name).copied().unwrap_or_default(), std::mem::take(i)))
+        .collect();
+    keyed.sort_by(|((pa, sa), a), ((pb, sb), b)| {
+        let ord = pa.cmp(pb).then(sa.total_cmp(sb)).reverse();
+        if lexical {
+            ord.then_with(|| natural_lexical_cmp(a.as_ref(), b.as_ref()))
+        } else {
+            ord
+        }
     });
+    for (slot, (_, item)) in items.iter_mut().zip(keyed) {
+        *slot = item
+    }
+    rank_sort(items)
 }
 
-fn cache_add(name: &str, item: &Item) {
+/// Move ranked items such as default handlers above the rest, keeping the order otherwise
+fn rank_sort(items: &mut [Item]) {
+    if items.iter().any(|i| i.rank.is_some()) {
+        items.sort_by_key(|i| i.rank.unwrap_or(usize::MAX))
+    }
+}
+
+/// Count a launch, first decaying the old count so long unused entries don't spring back to the top.
+/// Past `max` entries the lowest scoring unpinned ones are dropped, never the one just launched
+fn cache_add(name: &str, item: &Item, halflife: f32, max: Option<usize>) {
     let mut cache = cache_get(name);
+    let now = unix_now();
     let mut set = false;
     for line in cache.iter_mut() {
-        if line.1 == item.as_ref() {
-            line.0 = line.0.saturating_add(1); //optimistic lol
+        if line.name == item.as_ref() {
+            line.count = (line.score(now, halflife).round() as usize).saturating_add(1); //optimistic lol
+            line.last = Some(now);
             set = true;
         }
     }
     if !set {
-        cache.push((1, item.name.clone()))
+        cache.push(CacheEntry {
+            count: 1,
+            pinned: false,
+            last: Some(now),
+            name: item.name.to_string(),
+        })
+    }
+    if let Some(max) = max.filter(|m| cache.len() > *m) {
+        cache = cache_prune(cache, item.as_ref(), now, halflife, max)
     }
     cache_set(name, cache);
 }
 
+/// The `max` highest scoring entries, plus any pinned ones and `launched` even past `max`
+fn cache_prune(cache: Vec<CacheEntry>, launched: &str, now: u64, halflife: f32, max: usize) -> Vec<CacheEntry> {
+    let mut scored: Vec<(f64, CacheEntry)> = cache.into_iter().map(|e| (e.score(now, halflife), e)).collect();
+    scored.sort_by(|(sa, a), (sb, b)| {
+        (a.pinned, a.name == launched)
+            .cmp(&(b.pinned, b.name == launched))
+            .then(sa.total_cmp(sb))
+            .reverse()
+    });
+    scored
+        .into_iter()
+        .enumerate()
+        .filter(|(n, (_, e))| *n < max || e.pinned || e.name == launched)
+        .map(|(_, (_, e))| e)
+        .collect()
+}
+
 fn cache_del(name: &str, item: &Item) {
     cache_set(
         name,
         cache_get(name)
             .into_iter()
-            .filter(|(_n, s)| s != item.as_ref())
+            .filter(|e| e.name != item.as_ref())
             .collect(),
     );
 }
 
 // ### Cache FNS }}}
 
+// ### History FNS {{{
+
+/// Rotate to history.log.1 past this many bytes
+const HISTORY_MAX: u64 = 1024 * 1024;
+
+/// UTC ISO 8601 timestamp from unix seconds
+fn iso8601(secs: u64) -> String {
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // Howard Hinnant's civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Append a launch to the history log. Failures only warn since the launch already happened
+fn history_log(mode: &str, item: &Item, command: &std::process::Command) {
+    let Some(dir) = storage::state_dir() else {
+        eprintln!("Could not find state directory for history log");
+        return;
+    };
+    let file = dir.join("history.log");
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let line = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|s| s.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let line = format!("{}\t{}\t{}\t{}\n", iso8601(secs), mode, item, line);
+    if let Err(e) = history_append(&dir, &line, HISTORY_MAX) {
+        eprintln!("Could not write history log {}\n{}", file.display(), e)
+    }
+}
+
+/// Append `line` to `dir`/history.log, first moving it to history.log.1 once past `max` bytes.
+/// Launches at the same time take turns on a lock file, as the log itself is renamed away
+fn history_append(dir: &Path, line: &str, max: u64) -> std::io::Result<()> {
+    let file = dir.join("history.log");
+    std::fs::create_dir_all(dir)?;
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("history.log.lock"))?;
+    // released when closed
+    lock.lock()?;
+    if file.metadata().is_ok_and(|m| m.len() > max) {
+        std::fs::rename(&file, dir.join("history.log.1"))?
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file)?
+        .write_all(line.as_bytes())
+}
+
+// ### History FNS }}}
+
+#[derive(Default)]
 struct Linch {
     input: String,
     input_compiled: Option<Regex>,
     input_selected: bool,
+    /// Uncommitted IME composition, drawn after the input but never searched
+    preedit: String,
+    /// Selection as index, scroll, and name from before the search, restored once it's cleared
+    unfiltered: Option<(usize, usize, Name)>,
     index: usize,
     scroll: usize,
     hover: Option<usize>,
@@ -352,19 +1019,85 @@ struct Linch {
     custom: bool,
     cache: String,
     prompt: String,
+    /// Subcommand name for the prompt's `{mode}`
+    mode: String,
+    /// The list is the input's result, which Enter copies
+    calc: bool,
+    /// Only the input's first word is searched, the rest being arguments for the choice
+    arguments: bool,
     columns: usize,
     rows: usize,
     fg: Color32,
     bg: Color32,
     acc: Color32,
+    /// Hover outline and text
+    hov: Color32,
+    /// Behind selected input text
+    sel: Color32,
+    opacity: f32,
     scale: f32,
+    /// Unscaled window size
+    size: [f32; 2],
+    /// Fractions of the monitor to resize to once its size is known
+    fit_monitor: [Option<f32>; 2],
+    /// Whether the window was opened centered rather than at a given position
+    centered: bool,
+    /// Save the position on close for --remember-position
+    remember_position: bool,
+    /// Flag values and defaults under the theme file
+    theme_base: theme::Theme,
+    /// Theme options set by flags, which the theme file can't change
+    theme_fixed: Vec<&'static str>,
+    palette_from_accent: bool,
+    /// Selection fill and text, defaulting to the accent and background
+    selection: [Option<Color32>; 2],
+    /// Hover outline and text, overriding the palette's
+    hover_color: Option<Color32>,
+    /// Corner radius of the window and item frames before scaling
+    rounding: f32,
+    /// Window outline before scaling. Item outlines are two thirds of it
+    border_width: f32,
     literal: bool,
+    case_sensitive: bool,
     exit_unfocus: bool,
+    wrap: bool,
     icons: bool,
+    monochrome: bool,
+    /// Symbolic icons stand in for missing ones, tinted to the text color
+    symbolic_fallback: bool,
+    /// Source file of every loaded icon texture
+    icon_sources: HashMap<String, PathBuf>,
+    /// Icons to render again before they're next drawn, after a theme reload
+    stale_icons: HashSet<String>,
+    /// Monitor scale the icons were rendered for, as it changes when moving between monitors
+    pixels_per_point: f32,
+    /// Raster and display size overriding the row height
+    icon_size: Option<u32>,
+    /// Drawn for items without a resolved icon
+    fallback_icon: Option<String>,
+    /// Fraction of the cell width for the detail column, if shown
+    detail_width: Option<f32>,
+    match_detail: bool,
+    /// Comments under every name instead of beside the selection
+    descriptions: bool,
+    /// Name what Enter would launch at the end of the input row
+    launch_hint: bool,
+    sort: bool,
+    /// The full list while browsing an entry's actions
+    parent_items: Option<Vec<Item>>,
+    /// Whether NoDisplay items are listed, toggled by Ctrl+H
+    show_hidden: bool,
+    /// Days for a cache count to lose half its weight
+    cache_halflife: f32,
+    /// Entries kept in the cache
+    cache_max: Option<usize>,
+    /// Staged entries of `cache` when editing it, written on submit
+    edit: Option<Vec<CacheEntry>>,
 }
 
 impl Linch {
     // {{{
+    #[allow(clippy::too_many_arguments)]
     fn new(
         cc: &eframe::CreationContext<'_>,
         mut items: Vec<Item>,
@@ -372,27 +1105,272 @@ impl Linch {
         custom: bool,
         cache: String,
         prompt: String,
-        mut columns: usize,
+        mode: String,
+        columns: usize,
         rows: usize,
-        fg: Color32,
-        bg: Color32,
-        acc: Color32,
+        palette: [Color32; 5],
         opacity: f32,
         scale: f32,
+        theme_base: theme::Theme,
+        theme_fixed: Vec<&'static str>,
+        palette_from_accent: bool,
+        selection: [Option<Color32>; 2],
+        hover_color: Option<Color32>,
+        rounding: f32,
+        border_width: f32,
         literal: bool,
+        case_sensitive: bool,
         exit_unfocus: bool,
+        wrap: bool,
         icons: bool,
         monochrome: bool,
+        icon_theme: Option<String>,
+        icon_prefer: icons::Prefer,
+        symbolic_fallback: bool,
+        sort: bool,
+        edit: bool,
+        show_hidden: bool,
+        cache_halflife: f32,
+        cache_max: Option<usize>,
+        icon_cache: bool,
+        icon_size: Option<u32>,
+        fallback_icon: Option<String>,
+        detail_width: Option<f32>,
+        match_detail: bool,
+        descriptions: bool,
+        launch_hint: bool,
+        arguments: bool,
+        font: Option<String>,
         size: [f32; 2],
+        fit_monitor: [Option<f32>; 2],
+        centered: bool,
+        remember_position: bool,
     ) -> Self {
-        let style = cc.egui_ctx.style().as_ref().clone();
-        cc.egui_ctx.set_style(Style {
+        let [fg, bg, acc, hov, sel] = palette;
+        if !cache.is_empty() {
+            cache_apply(&cache, &mut items, sort, cache_halflife);
+        } else if sort {
+            items.sort_unstable_by(|a, b| natural_lexical_cmp(a.as_ref(), b.as_ref()));
+            rank_sort(&mut items);
+        }
+
+        let color_images = Mutex::new(HashMap::new());
+        let pixels_per_point = cc.egui_ctx.pixels_per_point();
+        let w = icon_px(icon_size, size, scale * pixels_per_point, rows);
+        let h = w;
+        if icons {
+            #[cfg(debug_assertions)]
+            let now = std::time::Instant::now();
+
+            let theme = icon_theme.or_else(icons::gtk_theme);
+            // themes size @2 directories for 200% displays
+            let icon_scale = (scale * pixels_per_point).round().max(1.0) as u32;
+            // everything that changes the rendered pixels besides the source file itself
+            let raster_key = |icon: &str| {
+                format!(
+                    "{}\t{}\t{}@{}\t{:?}\t{}\t{}",
+                    theme.as_deref().unwrap_or_default(),
+                    icon,
+                    w,
+                    icon_scale,
+                    icon_prefer,
+                    if monochrome { acc.to_hex() } else { String::new() },
+                    if symbolic_fallback { fg.to_hex() } else { String::new() }
+                )
+            };
+            // without a cache dir icons are looked up and rendered every launch
+            let files = storage::cache_file("icons.bin").zip(storage::cache_file("icon_paths"));
+            let icon_cache = icon_cache && files.is_some();
+            let (raster_file, paths_file) = files.unwrap_or_default();
+            let raster = if icon_cache && storage::usable(&raster_file) {
+                raster::RasterCache::load(&raster_file)
+            } else {
+                raster::RasterCache::default()
+            };
+            let rendered = std::sync::atomic::AtomicBool::new(false);
+
+            let paths = if icon_cache && storage::usable(&paths_file) {
+                icons::PathCache::load(&paths_file)
+            } else {
+                icons::PathCache::default()
+            };
+            // warm caches never need the themes read
+            let themes = std::sync::OnceLock::new();
+            let lookup = |icon: &str| {
+                let key = format!(
+                    "{}\t{}\t{}@{}\t{:?}\t{}",
+                    theme.as_deref().unwrap_or_default(),
+                    icon,
+                    w,
+                    icon_scale,
+                    icon_prefer,
+                    symbolic_fallback
+                );
+                paths.get_or(key, || {
+                    let themes =
+                        themes.get_or_init(|| icons::Themes::load(theme.as_deref(), w, icon_scale, icon_prefer));
+                    themes
+                        .lookup(icon)
+                        .or_else(|| icons::get_icon_loc(icon))
+                        .or_else(|| symbolic_fallback.then(|| themes.lookup_symbolic(icon)).flatten())
+                        // minimal systems may have no icon theme at all
+                        .or_else(|| {
+                            (Some(icon) == fallback_icon.as_deref())
+                                .then(raster::fallback_file)
+                                .flatten()
+                        })
+                })
+            };
+            items
+                .par_iter()
+                .flat_map_iter(|i| std::iter::once(i).chain(&i.actions))
+                .filter_map(|i| i.icon.as_ref())
+                .chain(fallback_icon.as_ref())
+                .for_each(|icon| {
+                    if !color_images.lock().unwrap().contains_key(icon) {
+                        if let Some(hit) = raster.get(&raster_key(icon)) {
+                            color_images.lock().unwrap().insert(icon.to_string(), hit);
+                        } else if let Some(path) = lookup(icon) {
+                            rendered.store(true, std::sync::atomic::Ordering::Relaxed);
+                            // failures are kept too so they aren't retried every launch
+                            let color_image = match raster::render(&path, w, h) {
+                                Ok(mut ci) => {
+                                    recolor(&path, &mut ci, monochrome, symbolic_fallback, fg, acc);
+                                    Some(ci)
+                                }
+                                Err(_e) => {
+                                    #[cfg(debug_assertions)]
+                                    println!("Icon {} failed to decode: {}", path.display(), _e);
+                                    None
+                                }
+                            };
+                            color_images
+                                .lock()
+                                .unwrap()
+                                .insert(icon.to_string(), (path, color_image));
+                        }
+                    }
+                });
+            #[cfg(debug_assertions)]
+            println!("Icons loaded in {:?}", now.elapsed());
+            if icon_cache {
+                if let Err(e) = paths.save(&paths_file) {
+                    eprintln!("Could not write icon path cache {}\n{}", paths_file.display(), e)
+                }
+            }
+
+            // rewrite when anything was rendered or cached icons are no longer used
+            let color_images = color_images.lock().unwrap();
+            if icon_cache && (rendered.into_inner() || raster.len() != color_images.len()) {
+                if let Err(e) = raster::RasterCache::save(
+                    &raster_file,
+                    color_images
+                        .iter()
+                        .map(|(icon, (path, ci))| (raster_key(icon), path.as_path(), ci.as_ref())),
+                ) {
+                    eprintln!("Could not write icon cache {}\n{}", raster_file.display(), e)
+                }
+            }
+        }
+
+        let (images, icon_sources) = load_textures(&cc.egui_ctx, color_images.into_inner().unwrap());
+        theme::watch(cc.egui_ctx.clone());
+
+        if let Some(font) = font {
+            match load_font(&font) {
+                Ok(data) => {
+                    let mut fonts = FontDefinitions::default();
+                    fonts.font_data.insert(font.clone(), FontData::from_owned(data));
+                    // the defaults stay behind it for any glyphs it lacks
+                    fonts
+                        .families
+                        .entry(FontFamily::Proportional)
+                        .or_default()
+                        .insert(0, font);
+                    cc.egui_ctx.set_fonts(fonts);
+                }
+                Err(e) => eprintln!("{}\nUsing the default font", e),
+            }
+        }
+
+        let linch = Self {
+            images,
+            custom,
+            prompt,
+            calc: mode == "calc",
+            mode,
+            bg,
+            fg,
+            acc,
+            hov,
+            sel,
+            opacity,
+            scale,
+            size,
+            fit_monitor,
+            centered,
+            remember_position,
+            theme_base,
+            theme_fixed,
+            palette_from_accent,
+            selection,
+            hover_color,
+            rounding,
+            border_width,
+            literal,
+            case_sensitive,
+            exit_unfocus,
+            wrap,
+            icons,
+            monochrome,
+            symbolic_fallback,
+            icon_sources,
+            stale_icons: HashSet::new(),
+            pixels_per_point,
+            icon_size,
+            fallback_icon,
+            detail_width,
+            match_detail,
+            descriptions,
+            launch_hint,
+            arguments,
+            sort,
+            parent_items: None,
+            edit: if edit { Some(cache_get(&cache)) } else { None },
+            show_hidden,
+            cache_halflife,
+            cache_max,
+            cache,
+            ..Self::headless(items, response, rows, columns)
+        };
+        linch.apply_style(&cc.egui_ctx);
+        linch
+    }
+
+    /// Only the list and its layout, with no window, icons, or cache behind it
+    fn headless(items: Vec<Item>, response: Arc<Mutex<Option<Item>>>, rows: usize, columns: usize) -> Self {
+        Self {
+            columns: ((items.len() as f32 / rows as f32).ceil() as usize).min(columns).max(1),
+            rows,
+            items,
+            response,
+            show_hidden: true,
+            opacity: 1.0,
+            scale: 1.0,
+            pixels_per_point: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn apply_style(&self, ctx: &Context) {
+        let style = ctx.style().as_ref().clone();
+        ctx.set_style(Style {
             wrap_mode: None,
             visuals: Visuals {
                 widgets: Widgets {
                     noninteractive: WidgetVisuals {
                         fg_stroke: Stroke {
-                            color: fg,
+                            color: self.fg,
                             ..Default::default()
                         },
                         ..style.visuals.widgets.noninteractive
@@ -400,21 +1378,21 @@ impl Linch {
                     ..style.visuals.widgets
                 },
                 selection: Selection {
-                    bg_fill: acc.gamma_multiply(0.5),
+                    bg_fill: self.sel,
                     stroke: Stroke {
                         width: 1.0, // seems fixed?
-                        color: acc,
+                        color: self.acc,
                     },
                 },
-                window_fill: bg.gamma_multiply(opacity),
+                window_fill: self.bg.gamma_multiply(self.opacity),
                 window_shadow: Shadow::NONE,
-                window_stroke: Stroke::new(3.0 * scale, acc),
-                window_rounding: Rounding::ZERO,
+                window_stroke: Stroke::new(self.border_width * self.scale, self.acc),
+                window_rounding: Rounding::same(self.rounding * self.scale),
                 ..style.visuals
             },
             spacing: Spacing {
                 item_spacing: (0.0, 0.0).into(),
-                window_margin: (4.0 * scale).into(),
+                window_margin: (4.0 * self.scale).into(),
                 button_padding: (0.0, 0.0).into(),
                 menu_margin: 0.0.into(),
                 indent: 0.0,
@@ -436,125 +1414,120 @@ impl Linch {
             },
             ..style
         });
+    }
 
-        if !cache.is_empty() {
-            cache_apply(&cache, &mut items);
-        } else {
-            items.sort_unstable_by(|a, b| natural_lexical_cmp(a.as_ref(), b.as_ref()))
+    /// Restyle the open window from `theme`, the theme file as just read again
+    fn reload_theme(&mut self, ctx: &Context, theme: theme::Theme) {
+        let look = theme.or(&self.theme_base, &self.theme_fixed);
+        let (Some(fg), Some(bg), Some(acc), Some(opacity), Some(scale)) =
+            (look.foreground, look.background, look.accent, look.opacity, look.scale)
+        else {
+            return;
+        };
+        let [fg, bg, acc, hov, sel] = palette(fg, bg, acc, self.palette_from_accent);
+        let resized = scale != self.scale;
+        if resized || (self.monochrome && acc != self.acc) || (self.symbolic_fallback && fg != self.fg) {
+            self.stale_icons.extend(self.icon_sources.keys().cloned())
         }
-
-        let color_images = Mutex::new(HashMap::new());
-        let acc_pixel = Rgba::from(acc);
-        let acc_pixel = [acc_pixel[0], acc_pixel[1], acc_pixel[2]];
-        let w = (size[1] * scale / (rows + 1) as f32 / 16.0).ceil() as u32 * 16;
-        let h = w;
-        if icons {
-            #[cfg(debug_assertions)]
-            let now = std::time::Instant::now();
-
-            items.par_iter().filter_map(|i| i.icon.as_ref()).for_each(|icon| {
-                if !color_images.lock().unwrap().contains_key(icon) {
-                    if let Some(path) = get_icon_loc(&icon) {
-                        if let Ok(mut file) = File::open(&path) {
-                            let mut data = Vec::new();
-                            if file.read_to_end(&mut data).is_ok() {
-                                let mut color_image = None;
-                                if path.extension() == Some(&OsStr::new("svg")) {
-                                    if let Ok(data) = usvg::Tree::from_data(&data, &usvg::Options::default()) {
-                                        let scale =
-                                            (w as f32 / data.size().width()).min(h as f32 / data.size().height());
-                                        let mut pixbuf = tiny_skia::Pixmap::new(w, h).unwrap();
-                                        resvg::render(
-                                            &data,
-                                            tiny_skia::Transform::from_scale(scale, scale),
-                                            &mut pixbuf.as_mut(),
-                                        );
-                                        color_image = Some(ColorImage::from_rgba_unmultiplied(
-                                            [pixbuf.width() as usize, pixbuf.height() as usize],
-                                            &pixbuf.take(),
-                                        ));
-                                    }
-                                } else {
-                                    if let Some(image) =
-                                        image::io::Reader::open(path).map(|r| r.decode().ok()).ok().flatten()
-                                    {
-                                        color_image = Some(ColorImage::from_rgba_unmultiplied(
-                                            [image.width() as usize, image.height() as usize],
-                                            &image.into_rgba8(),
-                                        ));
-                                    };
-                                }
-                                if let Some(mut ci) = color_image {
-                                    if monochrome {
-                                        let mut pixels: Vec<[f32; 4]> = ci
-                                            .pixels
-                                            .into_iter()
-                                            .map(|c32| Rgba::from(c32).to_rgba_unmultiplied())
-                                            .collect();
-
-                                        monochromatize(acc_pixel, &mut pixels, Space::LRGB);
-
-                                        ci.pixels = pixels
-                                            .into_iter()
-                                            .map(|p| {
-                                                Color32::from(Rgba::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
-                                            })
-                                            .collect();
-                                    }
-                                    color_images.lock().unwrap().insert(icon.to_string(), ci);
-                                }
-                            }
-                        }
-                    }
-                }
-            });
-            #[cfg(debug_assertions)]
-            println!("Icons loaded in {:?}", now.elapsed());
+        if scale != self.scale {
+            ctx.send_viewport_cmd(ViewportCommand::InnerSize(
+                (self.size[0] * scale, self.size[1] * scale).into(),
+            ))
         }
+        (self.fg, self.bg, self.acc, self.hov, self.sel) = (fg, bg, acc, hov, sel);
+        (self.opacity, self.scale) = (opacity, scale);
+        self.apply_style(ctx);
+    }
 
-        let mut images = HashMap::new();
-        for (k, v) in color_images.into_inner().unwrap().into_iter() {
-            let th = cc.egui_ctx.load_texture(&k, v, TextureOptions::default());
-            images.insert(k, th);
+    /// Resize to the --width and --height percentages once the monitor is known, keeping the center.
+    /// Only for when the work area couldn't be found before opening, as on Wayland
+    fn fit_to_monitor(&mut self, ctx: &Context) {
+        let (Some(monitor), outer) = ctx.input(|i| (i.viewport().monitor_size, i.viewport().outer_rect)) else {
+            return;
+        };
+        for (n, fraction) in std::mem::take(&mut self.fit_monitor).into_iter().enumerate() {
+            if let Some(f) = fraction {
+                self.size[n] = monitor[n] * f / self.scale
+            }
         }
+        self.size[1] = self.size[1].max(icon_height(self.icon_size, self.rows));
+        let size = Vec2::from(self.size) * self.scale;
+        if let Some(outer) = outer.filter(|_| self.centered) {
+            ctx.send_viewport_cmd(ViewportCommand::OuterPosition(outer.center() - size / 2.0))
+        }
+        ctx.send_viewport_cmd(ViewportCommand::InnerSize(size));
+        if self.icon_size.is_none() {
+            self.stale_icons.extend(self.icon_sources.keys().cloned())
+        }
+    }
 
-        columns = ((items.len() as f32 / rows as f32).ceil() as usize).min(columns).max(1);
-
-        Self {
-            input: String::new(),
-            input_compiled: None,
-            input_selected: false,
-            index: 0,
-            scroll: 0,
-            hover: None,
-            focused: false,
-            images,
-
-            items,
-            custom,
-            response,
-            cache,
-            prompt,
-            columns,
-            rows,
-            bg,
-            fg,
-            acc,
-            scale,
-            literal,
-            exit_unfocus,
-            icons,
+    /// Render stale icons that are about to be drawn
+    fn refresh_icons(&mut self) {
+        let area = self.rows * self.columns;
+        let visible: Vec<String> = self
+            .items_filter()
+            .skip(self.scroll * area)
+            .take(area)
+            .filter_map(|i| i.icon.clone())
+            .chain(self.fallback_icon.clone())
+            .filter(|i| self.stale_icons.contains(i))
+            .collect();
+        let w = icon_px(self.icon_size, self.size, self.scale * self.pixels_per_point, self.rows);
+        for icon in visible {
+            self.stale_icons.remove(&icon);
+            let (Some(path), Some(texture)) = (self.icon_sources.get(&icon), self.images.get_mut(&icon)) else {
+                continue;
+            };
+            if let Ok(mut ci) = raster::render(path, w, w) {
+                recolor(
+                    path,
+                    &mut ci,
+                    self.monochrome,
+                    self.symbolic_fallback,
+                    self.fg,
+                    self.acc,
+                );
+                texture.set(ci, TextureOptions::default())
+            }
         }
     }
 
     fn items_filter(&self) -> impl Iterator<Item = &Item> {
-        self.items.iter().filter(|s| {
-            if let Some(re) = &self.input_compiled {
-                re.is_match(s.as_ref())
-            } else {
-                s.as_ref().starts_with(&self.input)
-            }
-        })
+        // a result never contains the expression it came from
+        let matcher = move |t: &str| {
+            self.calc || search_matches(self.query(), self.input_compiled.as_ref(), t, self.case_sensitive)
+        };
+        let visible = move |s: &&Item| self.show_hidden || !s.no_display;
+        let secondary = move |s: &Item| {
+            s.comment.as_deref().is_some_and(matcher) || (self.match_detail && s.detail.as_deref().is_some_and(matcher))
+        };
+        // dividers stay while anything up to the next one matches
+        let section_matches = move |n: usize| {
+            self.items[n + 1..]
+                .iter()
+                .take_while(|s| !s.separator)
+                .filter(visible)
+                .any(|s| s.matches(matcher))
+        };
+        // items only matching by comment or detail come after everything else
+        self.items
+            .iter()
+            .enumerate()
+            .filter(move |(_, s)| visible(s))
+            .filter(move |(n, s)| {
+                if s.separator {
+                    section_matches(*n)
+                } else {
+                    s.matches(matcher)
+                }
+            })
+            .map(|(_, s)| s)
+            .chain(
+                self.items
+                    .iter()
+                    .filter(visible)
+                    .filter(move |s| !s.separator && !s.matches(matcher) && secondary(s)),
+            )
     }
 
     fn items_filtered(&self, count: usize, skip: usize) -> Vec<Item> {
@@ -564,101 +1537,443 @@ impl Linch {
     fn selected(&self) -> Option<Item> {
         self.items_filter()
             .nth(self.index + self.scroll * self.rows * self.columns)
+            .filter(|i| !i.separator)
             .cloned()
     }
 
+    /// Apply one frame of input to the selection, scroll, and lists
+    fn handle(&mut self, input: FrameInput) -> Action {
+        // {{{
+        let mut action = Action::None;
+        if let Some(text) = input.text {
+            // read before the list is filtered by the new text
+            let before = self
+                .input
+                .is_empty()
+                .then(|| self.selected().map(|i| i.name).unwrap_or_default());
+            self.input = text;
+            self.compile();
+            if self.calc {
+                self.calculate()
+            }
+            self.search_changed(before);
+        }
+        match input.focused {
+            Some(true) => self.focused = true,
+            Some(false) if self.focused && self.exit_unfocus => action = Action::Close,
+            _ => (),
+        }
+        match input.edit {
+            Some(Command::CountUp) => self.edit_selected(|e| e.count = e.count.saturating_add(1)),
+            Some(Command::CountDown) => self.edit_selected(|e| e.count = e.count.saturating_sub(1)),
+            Some(Command::TogglePin) => self.edit_selected(|e| e.pinned = !e.pinned),
+            _ => (),
+        }
+        let area = self.rows * self.columns;
+        // slots, so dividers included
+        let count = self.items_filter().count().saturating_sub(self.scroll * area);
+        match input.command {
+            Some(Command::OpenActions) => self.open_actions(),
+            Some(Command::Submit) => action = Action::Submit,
+            Some(Command::Back) => action = if self.close_actions() { action } else { Action::Close },
+            Some(Command::ToggleInput) => self.input_selected = !self.input_selected,
+            Some(Command::Delete) => self.del(),
+            Some(Command::ToggleHidden) => {
+                self.show_hidden = !self.show_hidden;
+                self.index = 0;
+                self.scroll = 0
+            }
+            _ if input.scroll < 0.0 && count > area => {
+                self.scroll += 1;
+                self.index = self.index.min(count - area - 1)
+            }
+            _ if input.scroll > 0.0 && self.scroll > 0 => self.scroll -= 1,
+            _ => (),
+        }
+        let mut forward = true;
+        let wheel = match input.scroll_x {
+            x if x < 0.0 => Some(Command::Right),
+            x if x > 0.0 => Some(Command::Left),
+            _ => None,
+        };
+        let arrow = input.arrow.filter(|_| !self.input_selected);
+        let before = (self.scroll, self.index);
+        match arrow.or(wheel) {
+            // a single row has no column to move within, so step through every item
+            Some(Command::Up) if self.rows == 1 => {
+                forward = false;
+                if self.index > 0 {
+                    self.index -= 1
+                } else if self.scroll > 0 {
+                    self.scroll -= 1;
+                    self.index = area - 1
+                }
+            }
+            Some(Command::Down) if self.rows == 1 => {
+                if self.index + 1 < count.min(area) {
+                    self.index += 1
+                } else if count > area {
+                    self.scroll += 1;
+                    self.index = 0
+                }
+            }
+            Some(Command::Up) => {
+                forward = false;
+                if !self.index.is_multiple_of(self.rows) {
+                    self.index -= 1
+                } else if self.scroll > 0 {
+                    self.scroll -= 1;
+                    self.index += self.rows - 1
+                }
+            }
+            Some(Command::Down) => {
+                if self.index % self.rows < self.rows - 1 && self.index < count.saturating_sub(1) {
+                    self.index += 1
+                } else if count > area {
+                    self.scroll += 1;
+                    self.index = (self.index + 1 - self.rows).min(count - area - 1)
+                }
+            }
+            Some(Command::Right) if self.index + self.rows < count.min(area) => self.index += self.rows,
+            Some(Command::Left) if self.index >= self.rows => {
+                forward = false;
+                self.index -= self.rows
+            }
+            // past the outer columns rows continue on the neighbouring page,
+            // so a single column pages and a single row steps through every item
+            Some(Command::Right) if count > area => {
+                self.scroll += 1;
+                self.index = (self.index % self.rows).min(count - area - 1)
+            }
+            Some(Command::Left) if self.scroll > 0 => {
+                forward = false;
+                self.scroll -= 1;
+                self.index += area - self.rows
+            }
+            _ => (),
+        }
+        self.skip_separator(forward);
+        // keys only, as wheels would spin straight past the ends.
+        // checked after skipping dividers, since Up onto a leading one is bounced straight back
+        if self.wrap && arrow.is_some() && (self.scroll, self.index) == before {
+            let last = self.items_filter().count().saturating_sub(1);
+            let first = self.items_filter().position(|i| !i.separator).unwrap_or(0);
+            let at = self.scroll * area + self.index;
+            // the last slot is never a divider, as one is only kept with items after it
+            match arrow {
+                Some(Command::Up) if at <= first => (self.scroll, self.index) = (last / area, last % area),
+                Some(Command::Left) => (self.scroll, self.index) = (last / area, last % area),
+                Some(Command::Down) if at == last => (self.scroll, self.index) = (0, 0),
+                Some(Command::Right) => (self.scroll, self.index) = (0, 0),
+                _ => (),
+            }
+            self.skip_separator(true);
+        }
+        action
+    } // }}}
+
+    /// Move the selection off a divider, preferring the way it was going
+    fn skip_separator(&mut self, forward: bool) {
+        let area = self.rows * self.columns;
+        let slots: Vec<bool> = self
+            .items_filter()
+            .skip(self.scroll * area)
+            .take(area)
+            .map(|i| i.separator)
+            .collect();
+        if !slots.get(self.index).copied().unwrap_or(false) {
+            return;
+        }
+        let after = (self.index + 1..slots.len()).find(|n| !slots[*n]);
+        let before = (0..self.index).rev().find(|n| !slots[*n]);
+        if let Some(n) = if forward { after.or(before) } else { before.or(after) } {
+            self.index = n
+        }
+    }
+
     fn compile(&mut self) {
         if !self.literal {
-            self.input_compiled = Regex::new(&(String::from("(?i)") + &self.input)).ok()
+            self.input_compiled = matching::compile(self.query(), self.case_sensitive)
         }
     }
 
+    /// The search fell back to a literal prefix as the query isn't a valid regex
+    fn invalid_regex(&self) -> bool {
+        !self.literal && !self.calc && !self.query().is_empty() && self.input_compiled.is_none()
+    }
+
+    /// The searched part of the input
+    fn query(&self) -> &str {
+        match self.input.split_once(char::is_whitespace) {
+            Some((query, _)) if self.arguments => query,
+            _ => &self.input,
+        }
+    }
+
+    /// Replace the list with the input's value, leaving it empty while the input isn't valid
+    fn calculate(&mut self) {
+        self.items = calc::eval(&self.input)
+            .map(|value| Item {
+                name: calc::format(value).into(),
+                detail: Some(self.input.clone()),
+                ..Default::default()
+            })
+            .into_iter()
+            .collect();
+    }
+
     fn set(&self) {
+        if let Some(entries) = self.edit.as_ref() {
+            cache_set(&self.cache, entries.clone());
+            return;
+        }
         let mut item = self.selected();
         if let Some(item) = item.as_ref() {
             if !self.cache.is_empty() {
-                cache_add(&self.cache, item)
+                cache_add(&self.cache, item, self.cache_halflife, self.cache_max)
             }
         }
         if self.custom && item.is_none() && !self.input.is_empty() {
             item = Some(Item {
-                name: self.input.clone(),
-                file: None,
-                exec: None,
-                path: None,
-                icon: None,
-                hidden: false,
+                name: self.input.as_str().into(),
+                ..Default::default()
             })
         }
+        // binaries have no Exec of their own, so it carries the typed arguments
+        if let Some((item, (_, rest))) = item.as_mut().zip(self.input.split_once(char::is_whitespace)) {
+            if self.arguments && self.edit.is_none() {
+                item.exec = Some(rest.trim().to_string()).filter(|r| !r.is_empty())
+            }
+        }
         *self.response.lock().unwrap() = item
     }
 
+    /// Back to the top while searching, or to where the search began once it's cleared.
+    /// `before` is the selection if the input was empty before this change
+    fn search_changed(&mut self, before: Option<Name>) {
+        if let Some(name) = before.filter(|_| !self.input.is_empty()) {
+            self.unfiltered = Some((self.index, self.scroll, name));
+        }
+        let (mut index, mut scroll) = (0, 0);
+        if let Some((i, s, name)) = self.unfiltered.take_if(|_| self.input.is_empty()) {
+            let area = self.rows * self.columns;
+            let count = self.items_filter().count();
+            // the item may be gone if the list changed, so fall back to the same slot
+            let slot = self
+                .items_filter()
+                .position(|item| item.name == name && !item.separator)
+                .unwrap_or((s * area + i).min(count.saturating_sub(1)));
+            (index, scroll) = (slot % area, slot / area);
+        }
+        self.index = index;
+        self.scroll = scroll;
+    }
+
+    /// Swap the list for the selection's desktop actions, if it has any
+    fn open_actions(&mut self) {
+        if let Some(item) = self.selected().filter(|i| !i.actions.is_empty()) {
+            self.parent_items = Some(std::mem::replace(&mut self.items, item.actions));
+            self.input.clear();
+            self.compile();
+            self.unfiltered = None;
+            self.index = 0;
+            self.scroll = 0;
+        }
+    }
+
+    /// Return from an action list to the full list. False if not in one
+    fn close_actions(&mut self) -> bool {
+        if let Some(items) = self.parent_items.take() {
+            self.items = items;
+            self.input.clear();
+            self.compile();
+            self.unfiltered = None;
+            self.index = 0;
+            self.scroll = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Change the selection's staged cache entry and refresh its badge
+    fn edit_selected(&mut self, f: impl FnOnce(&mut CacheEntry)) {
+        if let (Some(item), Some(entries)) = (self.selected(), self.edit.as_mut()) {
+            if let Some(entry) = entries.iter_mut().find(|e| *e.name == *item.name) {
+                f(entry);
+                let badge = entry.badge();
+                for i in self.items.iter_mut().filter(|i| i.name == item.name) {
+                    i.badge = Some(badge.clone())
+                }
+            }
+        }
+    }
+
     fn del(&mut self) {
-        if !self.cache.is_empty() {
+        if self.edit.is_some() {
+            if let Some(item) = self.selected() {
+                if let Some(entries) = self.edit.as_mut() {
+                    entries.retain(|e| *e.name != *item.name)
+                }
+                self.items.retain(|i| i.name != item.name);
+                if self.selected().is_none() {
+                    self.index = self.index.saturating_sub(1)
+                }
+            }
+        } else if !self.cache.is_empty() {
             if let Some(item) = self.selected() {
                 cache_del(&self.cache, &item);
-                cache_apply(&self.cache, &mut self.items)
+                cache_apply(&self.cache, &mut self.items, self.sort, self.cache_halflife)
             }
         }
     }
 } // }}}
 
+/// A key press as the launcher understands it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    OpenActions,
+    Submit,
+    /// Leave the action list, else close
+    Back,
+    ToggleInput,
+    Delete,
+    ToggleHidden,
+    Up,
+    Down,
+    Left,
+    Right,
+    CountUp,
+    CountDown,
+    TogglePin,
+}
+
+/// One frame of input already read out of egui, so state changes don't need a window
+#[derive(Default)]
+struct FrameInput {
+    focused: Option<bool>,
+    /// Only used while editing a cache
+    edit: Option<Command>,
+    /// At most one per frame. Scrolling only applies without one
+    command: Option<Command>,
+    /// Vertical wheel, paging
+    scroll: f32,
+    /// Horizontal wheel, moving a column like the arrow keys
+    scroll_x: f32,
+    arrow: Option<Command>,
+    /// The whole input after an edit, applied before any keys
+    text: Option<String>,
+}
+
+/// What the window should do after a frame's input
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+    None,
+    Close,
+    /// Respond with the selection, then close
+    Submit,
+}
+
 impl App for Linch {
     // {{{
     fn clear_color(&self, _visuals: &Visuals) -> [f32; 4] {
         Color32::TRANSPARENT.to_normalized_gamma_f32()
     }
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
-        let mut close = false;
-        let area = self.rows * self.columns;
-        let count = self.items_filter().count() - self.scroll * area;
-        ctx.input_mut(|i| {
-            match i.viewport().focused {
-                Some(true) => self.focused = true,
-                Some(false) => {
-                    if self.focused && self.exit_unfocus {
-                        close = true
-                    }
+        if theme::take_reload() {
+            self.reload_theme(ctx, theme::get())
+        }
+        if self.fit_monitor.iter().any(Option::is_some) {
+            self.fit_to_monitor(ctx)
+        }
+        if ctx.pixels_per_point() != self.pixels_per_point {
+            self.pixels_per_point = ctx.pixels_per_point();
+            self.stale_icons.extend(self.icon_sources.keys().cloned())
+        }
+        if !self.stale_icons.is_empty() {
+            self.refresh_icons()
+        }
+        let composing = !self.preedit.is_empty();
+        let mut cancel_ime = false;
+        let input = ctx.input_mut(|i| {
+            // the TextEdit would write compositions into the input, so it only sees commits as text
+            i.events.retain_mut(|e| match e {
+                Event::Ime(ImeEvent::Commit(t)) if t != "\n" && t != "\r" => {
+                    self.preedit.clear();
+                    *e = Event::Text(std::mem::take(t));
+                    true
                 }
-                None => (),
-            }
-            if i.consume_key(Modifiers::NONE, Key::Enter) {
-                self.set();
-                close = true
-            } else if i.consume_key(Modifiers::NONE, Key::Escape) {
-                close = true
-            } else if i.consume_key(Modifiers::NONE, Key::Tab) {
-                self.input_selected = !self.input_selected;
-            } else if i.consume_key(Modifiers::NONE, Key::Delete) {
-                self.del()
-            } else if i.raw_scroll_delta.y < 0.0 && count > area {
-                self.scroll += 1;
-                self.index = self.index.min(count - area - 1)
-            } else if i.raw_scroll_delta.y > 0.0 && self.scroll > 0 {
-                self.scroll -= 1
-            }
-            if !self.input_selected {
-                if i.consume_key(Modifiers::NONE, Key::ArrowUp) {
-                    if self.index % self.rows != 0 {
-                        self.index -= 1
-                    } else if self.scroll > 0 {
-                        self.scroll -= 1;
-                        self.index += self.rows - 1
+                Event::Ime(ime) => {
+                    match ime {
+                        ImeEvent::Preedit(t) if t != "\n" && t != "\r" => self.preedit = t.clone(),
+                        ImeEvent::Preedit(_) => (),
+                        _ => self.preedit.clear(),
                     }
-                } else if i.consume_key(Modifiers::NONE, Key::ArrowDown) {
-                    if self.index % self.rows < self.rows - 1 && self.index < count.saturating_sub(1) {
-                        self.index += 1
-                    } else if count > area {
-                        self.scroll += 1;
-                        self.index = (self.index + 1 - self.rows).min(count - area - 1)
-                    }
-                } else if i.consume_key(Modifiers::NONE, Key::ArrowRight) && self.index + self.rows < count.min(area) {
-                    self.index += self.rows
-                } else if i.consume_key(Modifiers::NONE, Key::ArrowLeft) && self.index >= self.rows {
-                    self.index -= self.rows
+                    false
                 }
+                _ => true,
+            });
+            // keys typed mid composition belong to the IME
+            if composing && i.consume_key(Modifiers::NONE, Key::Escape) {
+                self.preedit.clear();
+                cancel_ime = true
             }
+            let mut input = FrameInput {
+                focused: i.viewport().focused,
+                scroll: i.raw_scroll_delta.y,
+                // egui already turns shift+wheel into this
+                scroll_x: i.raw_scroll_delta.x,
+                ..Default::default()
+            };
+            if self.edit.is_some() {
+                // +/- adjust counts, so they must not reach the search field
+                i.events
+                    .retain(|e| !matches!(e, Event::Text(t) if t == "+" || t == "-"));
+                input.edit = if i.consume_key(Modifiers::NONE, Key::Plus) {
+                    Some(Command::CountUp)
+                } else if i.consume_key(Modifiers::NONE, Key::Minus) {
+                    Some(Command::CountDown)
+                } else if i.consume_key(Modifiers::CTRL, Key::P) {
+                    Some(Command::TogglePin)
+                } else {
+                    None
+                };
+            }
+            // shift first as the unmodified check also matches shift
+            input.command = [
+                (Modifiers::SHIFT, Key::Enter, Command::OpenActions),
+                (Modifiers::NONE, Key::Enter, Command::Submit),
+                (Modifiers::NONE, Key::Escape, Command::Back),
+                (Modifiers::NONE, Key::Tab, Command::ToggleInput),
+                (Modifiers::NONE, Key::Delete, Command::Delete),
+                (Modifiers::CTRL, Key::H, Command::ToggleHidden),
+            ]
+            .into_iter()
+            .find_map(|(m, k, c)| i.consume_key(m, k).then_some(c));
+            // arrows move the text cursor while the input is selected
+            if self.input_selected == (input.command == Some(Command::ToggleInput)) {
+                input.arrow = [
+                    (Key::ArrowUp, Command::Up),
+                    (Key::ArrowDown, Command::Down),
+                    (Key::ArrowRight, Command::Right),
+                    (Key::ArrowLeft, Command::Left),
+                ]
+                .into_iter()
+                .find_map(|(k, c)| i.consume_key(Modifiers::NONE, k).then_some(c));
+            }
+            input
         });
+        let mut close = match self.handle(input) {
+            Action::None => false,
+            Action::Close => true,
+            Action::Submit => {
+                self.set();
+                true
+            }
+        };
+        // slots include dividers, matches don't
+        let matches = self.items_filter().filter(|i| !i.separator).count();
+        let total = self.items.iter().filter(|i| !i.separator).count();
         CentralPanel::default()
             .frame(Frame::window(&ctx.style()))
             .show(ctx, |ui| {
@@ -666,10 +1981,8 @@ impl App for Linch {
                 // weirdly the trailing edge is fatter?
                 // also bottom doesnt scale properly with -s 0.5...
                 let marg = ui.spacing().window_margin.top / 2.0;
-                let (x, y) = match ui.available_size() {
-                    // it works though
-                    Vec2 { x, y } => (x - marg, y - marg),
-                };
+                // it works though
+                let (x, y) = (ui.available_size().x - marg, ui.available_size().y - marg);
                 let sx = x / self.columns as f32;
                 let sy = y / (self.rows + 1) as f32;
                 let font = sy * 0.75;
@@ -677,29 +1990,74 @@ impl App for Linch {
                 let (tecol, hicol) = if self.input_selected {
                     (self.acc, self.fg)
                 } else {
-                    (self.fg, self.acc)
+                    (self.fg, self.selection[0].unwrap_or(self.acc))
                 };
                 Frame::none() // the default frame isn't colorable?
                     .stroke(Stroke {
-                        width: 2.0 * self.scale,
-                        color: tecol,
+                        width: self.border_width * 2.0 / 3.0 * self.scale,
+                        color: if self.invalid_regex() {
+                            ui.visuals().error_fg_color
+                        } else {
+                            tecol
+                        },
                     })
+                    .rounding(self.rounding * self.scale)
                     .outer_margin(1.0 * self.scale)
                     .show(ui, |ui| {
+                        let mut text = self.input.clone();
                         let response = ui.add_sized(
                             Vec2 { x, y: sy },
-                            TextEdit::singleline(&mut self.input)
+                            TextEdit::singleline(&mut text)
                                 .frame(false)
                                 .font(FontId::proportional(font))
                                 .text_color(tecol)
                                 // hint color == gray_out(noninteractive_color)
-                                .hint_text(&self.prompt)
+                                .hint_text(format_prompt(&self.prompt, matches, total, &self.mode))
                                 .lock_focus(true),
                         );
                         if response.changed() {
-                            self.compile();
-                            self.index = 0;
-                            self.scroll = 0;
+                            self.handle(FrameInput {
+                                text: Some(text),
+                                ..Default::default()
+                            });
+                        }
+                        if !self.preedit.is_empty() {
+                            let font = FontId::proportional(font);
+                            let start =
+                                ui.fonts(|f| f.layout_no_wrap(self.input.clone(), font.clone(), tecol).size().x);
+                            let painted = ui.painter().text(
+                                response.rect.left_center() + Vec2::new(start, 0.0),
+                                Align2::LEFT_CENTER,
+                                &self.preedit,
+                                font,
+                                tecol,
+                            );
+                            ui.painter()
+                                .hline(painted.x_range(), painted.bottom(), Stroke::new(self.scale, tecol));
+                        }
+                        // the cache editor saves on Enter instead of launching
+                        if let Some(item) = self.selected().filter(|_| self.launch_hint && self.edit.is_none()) {
+                            let typed = if self.input.is_empty() {
+                                format_prompt(&self.prompt, matches, total, &self.mode)
+                            } else {
+                                self.input.clone() + &self.preedit
+                            };
+                            let used =
+                                ui.fonts(|f| f.layout_no_wrap(typed, FontId::proportional(font), tecol).size().x);
+                            let gap = font;
+                            // truncated rather than drawn over the text
+                            let mut job = LayoutJob::simple_singleline(
+                                format!("→ {}", item.name),
+                                FontId::proportional(font * 0.6),
+                                tecol.gamma_multiply(0.6),
+                            );
+                            job.wrap = TextWrapping::truncate_at_width(response.rect.width() - used - gap);
+                            let galley = ui.fonts(|f| f.layout_job(job));
+                            if used + gap < response.rect.width() {
+                                let pos =
+                                    response.rect.right_center() - Vec2::new(galley.size().x, galley.size().y / 2.0);
+                                ui.painter().galley(pos, galley, tecol);
+                            }
                         }
                         if response.clicked() {
                             self.input_selected = true;
@@ -718,53 +2076,162 @@ impl App for Linch {
                         for r in 0..self.rows {
                             for c in 0..self.columns {
                                 let n = r + self.rows * c;
-                                if let Some(i) = items.get(n) {
+                                if let Some(i) = items.get(n).filter(|i| i.separator) {
+                                    let rect = ui.allocate_exact_size(Vec2 { x: sx, y: sy }, Sense::hover()).0;
+                                    let painter = ui.painter().with_clip_rect(rect);
+                                    let stroke = Stroke::new(self.scale, self.acc);
+                                    let y = rect.center().y;
+                                    if i.name.is_empty() {
+                                        painter.hline(rect.x_range(), y, stroke);
+                                    } else {
+                                        let label = painter.text(
+                                            rect.center(),
+                                            Align2::CENTER_CENTER,
+                                            &i.name,
+                                            FontId::proportional(font * 0.75),
+                                            self.acc,
+                                        );
+                                        let gap = font / 4.0;
+                                        painter.hline(rect.left()..=label.left() - gap, y, stroke);
+                                        painter.hline(label.right() + gap..=rect.right(), y, stroke);
+                                    }
+                                } else if let Some(i) = items.get(n) {
                                     let mut stroke = Stroke::NONE;
                                     let mut text = ui.style().visuals.text_color();
                                     let mut fill = Color32::TRANSPARENT;
                                     let mut submit = false;
                                     if self.index == n {
-                                        text = self.bg;
+                                        text = self.selection[1].unwrap_or(self.bg);
                                         submit = true;
                                         fill = hicol;
                                     } else if self.hover == Some(n) {
+                                        let color = self.hover_color.unwrap_or(self.hov);
                                         stroke = Stroke {
-                                            color: self.acc,
-                                            width: 2.0 * self.scale,
+                                            color,
+                                            width: self.border_width * 2.0 / 3.0 * self.scale,
                                         };
-                                        text = self.acc;
+                                        text = color;
+                                    }
+                                    if i.no_display {
+                                        text = text.gamma_multiply(0.5)
                                     }
                                     let response = Frame::none()
                                         .stroke(stroke)
                                         .fill(fill)
+                                        .rounding(self.rounding * self.scale)
                                         .inner_margin(2.0 * self.scale)
                                         .show(ui, |ui| {
                                             let mut shrink2 = Vec2 { x: 0.0, y: 0.0 };
                                             if self.icons {
-                                                shrink2 = Vec2 {
-                                                    x: ui.available_height(),
-                                                    y: 0.0,
-                                                };
-                                                match i.icon.as_ref().map(|i| self.images.get(i)).flatten() {
-                                                    Some(image) => drop(ui.add(Image::new(image).fit_to_exact_size(
-                                                        (ui.available_height(), ui.available_height()).into(),
-                                                    ))),
-                                                    None => drop(ui.allocate_space(Vec2::splat(ui.available_height()))),
+                                                // never taller than the row so it can't overlap the text
+                                                let side = self.icon_size.map_or(ui.available_height(), |s| {
+                                                    (s as f32 * self.scale).min(ui.available_height())
+                                                });
+                                                shrink2 = Vec2 { x: side, y: 0.0 };
+                                                let slot = ui
+                                                    .allocate_exact_size(
+                                                        Vec2 {
+                                                            x: side,
+                                                            y: ui.available_height(),
+                                                        },
+                                                        Sense::hover(),
+                                                    )
+                                                    .0;
+                                                if let Some(image) =
+                                                    i.icon.as_ref().and_then(|i| self.images.get(i)).or_else(|| {
+                                                        self.fallback_icon.as_ref().and_then(|i| self.images.get(i))
+                                                    })
+                                                {
+                                                    Image::new(image).paint_at(
+                                                        ui,
+                                                        Rect::from_center_size(slot.center(), Vec2::splat(side)),
+                                                    );
+                                                } else if let Some(letter) = i.name.chars().next() {
+                                                    // keeps rows even when not even the fallback icon resolved
+                                                    ui.painter().text(
+                                                        slot.center(),
+                                                        Align2::CENTER_CENTER,
+                                                        letter.to_uppercase(),
+                                                        FontId::proportional(side * 0.6),
+                                                        text.gamma_multiply(0.5),
+                                                    );
                                                 }
                                             }
                                             // manually paint text to avoid overallocation
-                                            ui.allocate_painter(
-                                                ui.available_size(),
-                                                Sense::hover(), // 3 false
-                                            )
-                                            .1
-                                            .text(
-                                                ui.max_rect().shrink2(shrink2).left_center(),
-                                                Align2::LEFT_CENTER,
-                                                i,
-                                                FontId::proportional(font),
-                                                text,
-                                            );
+                                            let painter = ui
+                                                .allocate_painter(
+                                                    ui.available_size(),
+                                                    Sense::hover(), // 3 false
+                                                )
+                                                .1;
+                                            // each column is clipped on its own so long text can't overlap
+                                            let (cell, detail) = match self.detail_width {
+                                                Some(f) => {
+                                                    let (l, r) = ui.max_rect().split_left_right_at_x(
+                                                        ui.max_rect().right() - ui.max_rect().width() * f,
+                                                    );
+                                                    (l, Some(r))
+                                                }
+                                                None => (ui.max_rect(), None),
+                                            };
+                                            if let (Some(rect), Some(detail)) = (detail, i.detail.as_ref()) {
+                                                painter.with_clip_rect(rect).text(
+                                                    rect.right_center(),
+                                                    Align2::RIGHT_CENTER,
+                                                    detail,
+                                                    FontId::proportional(font * 0.75),
+                                                    text.gamma_multiply(0.6),
+                                                );
+                                            }
+                                            let painter = painter.with_clip_rect(cell);
+                                            let description = i.comment.as_ref().filter(|_| self.descriptions);
+                                            let rect = if let Some(comment) = description {
+                                                // both lines together take the height of one name
+                                                let anchor = cell.shrink2(shrink2).left_center();
+                                                painter.text(
+                                                    anchor,
+                                                    Align2::LEFT_TOP,
+                                                    comment,
+                                                    FontId::proportional(font * 0.45),
+                                                    text.gamma_multiply(0.6),
+                                                );
+                                                painter.text(
+                                                    anchor,
+                                                    Align2::LEFT_BOTTOM,
+                                                    i,
+                                                    FontId::proportional(font * 0.6),
+                                                    text,
+                                                )
+                                            } else {
+                                                painter.text(
+                                                    cell.shrink2(shrink2).left_center(),
+                                                    Align2::LEFT_CENTER,
+                                                    i,
+                                                    FontId::proportional(font),
+                                                    text,
+                                                )
+                                            };
+                                            if let Some(badge) = i.badge.as_ref() {
+                                                painter.text(
+                                                    cell.right_center(),
+                                                    Align2::RIGHT_CENTER,
+                                                    badge,
+                                                    FontId::proportional(font * 0.75),
+                                                    text.gamma_multiply(0.6),
+                                                );
+                                            }
+                                            // only the selection shows its comment to keep the grid compact
+                                            if let Some(comment) =
+                                                i.comment.as_ref().filter(|_| self.index == n && !self.descriptions)
+                                            {
+                                                painter.text(
+                                                    rect.right_center() + Vec2 { x: font / 2.0, y: 0.0 },
+                                                    Align2::LEFT_CENTER,
+                                                    comment,
+                                                    FontId::proportional(font * 0.75),
+                                                    text.gamma_multiply(0.6),
+                                                );
+                                            }
                                         })
                                         .response
                                         .interact(Sense::click());
@@ -790,39 +2257,236 @@ impl App for Linch {
                         }
                     });
             });
+        if cancel_ime {
+            // dropping the IME for a frame makes it abandon the composition
+            ctx.output_mut(|o| o.ime = None);
+            ctx.request_repaint()
+        }
         if close {
+            if self.remember_position {
+                if let Some(outer) = ctx.input(|i| i.viewport().outer_rect) {
+                    position_set(outer.min)
+                }
+            }
             ctx.send_viewport_cmd(ViewportCommand::Close)
         }
     }
 } // }}}
 
-#[derive(Subcommand)]
+#[derive(Clone, Subcommand)]
 enum LinchCmd {
-    /// Launch a binary directly. Scans PATH by default
-    Bin,
+    /// Launch a binary directly. Scans PATH by default.
+    /// Search keywords and renames are read from $XDG_CONFIG_HOME/linch/aliases
+    /// as `keyword = binary` and `binary -> Display Name` lines
+    Bin {
+        /// Only show binaries listed one per line in $XDG_CONFIG_HOME/linch/binlist,
+        /// in the order listed
+        #[arg(long)]
+        only_listed: bool,
+
+        /// With --only-listed, show listed binaries missing from PATH dimmed instead of skipping them
+        #[arg(long)]
+        show_missing: bool,
+
+        /// Recolor icons shown with --icons to monochrome style accent
+        #[arg(long)]
+        monochrome: bool,
+
+        /// Print the items instead of opening a window, one per line as
+        /// name, file, exec, icon, and NoDisplay separated by tabs
+        #[arg(long)]
+        list: bool,
+
+        /// With --list, print one JSON object per line instead
+        #[arg(long, requires = "list")]
+        json: bool,
+
+        /// Search only the input's first word and pass the rest to the binary as arguments,
+        /// so `nvim notes.txt` opens a file. Words split on whitespace unless inside
+        /// 'single' or "double" quotes, and a backslash escapes the next character outside single quotes
+        #[arg(long)]
+        args: bool,
+    },
     /// Launch a desktop application.
     App {
-        /// Show all entries, including hidden and technical
+        /// Show all entries, including NoDisplay, technical, those whose TryExec isn't installed,
+        /// and those limited to other desktops by OnlyShowIn/NotShowIn.
+        /// Ctrl+H toggles them while open.
+        /// Entries deleted with Hidden=true are never shown
         #[arg(long)]
         all: bool,
 
-        /// Recolor icons to monochrome style accent. Strongly recommended to have a scalable
-        /// theme, as PNGs take 10x longer to recolor than SVGs
+        /// Recolor icons to monochrome style accent
         #[arg(long)]
         monochrome: bool,
+
+        /// Only show entries in this category, such as Game or Utility.
+        /// May be repeated to show entries in any of them
+        #[arg(long = "category")]
+        categories: Vec<String>,
+
+        /// Spawn DBusActivatable entries like any other instead of activating them over D-Bus
+        #[arg(long)]
+        no_dbus_activation: bool,
+
+        /// Show entries running the same command once, such as X11 and Wayland variants.
+        /// The others are listed with its actions
+        #[arg(long)]
+        merge_by_exec: bool,
+
+        /// Only show entries whose MimeType includes this type, such as application/pdf
+        #[arg(long)]
+        mime: Option<String>,
+
+        /// Files passed to the chosen entry through its Exec field codes
+        files: Vec<PathBuf>,
+
+        /// Explain on stderr why each desktop entry found was shown, skipped, or shadowed
+        #[arg(long)]
+        verbose: bool,
+
+        /// Environment for entries preferring the non default GPU when started without a launcher.
+        /// May be repeated. Defaults to DRI_PRIME=1 and NVIDIA's offload variables if its driver is loaded
+        #[arg(long, value_name = "VAR=VALUE", value_parser = parse_env)]
+        gpu_env: Vec<(String, String)>,
+
+        /// With --merge-by-exec, compare only the program and not its arguments
+        #[arg(long, requires = "merge_by_exec")]
+        merge_ignore_args: bool,
+
+        /// Print the items instead of opening a window, one per line as
+        /// name, file, exec, icon, and NoDisplay separated by tabs
+        #[arg(long)]
+        list: bool,
+
+        /// With --list, print one JSON object per line instead
+        #[arg(long, requires = "list")]
+        json: bool,
     },
     /// dmenu-like choices from stdin lines. No choices will allow custom input
-    Dmenu,
+    Dmenu {
+        /// Print the zero based line number of the choice instead of its text, or -1 for custom input
+        #[arg(long)]
+        index: bool,
+
+        /// Lines starting with this marker become dividers, labeled by the rest of the line
+        /// as in `--- Recent ---`. Keeps the input order
+        #[arg(long, value_name = "MARKER", num_args = 0..=1, default_missing_value = "---")]
+        separator: Option<String>,
+
+        /// Split the input on NUL bytes instead of newlines, as from `find -print0`
+        #[arg(long)]
+        read0: bool,
+
+        /// Read one JSON object per line instead, as in
+        /// `{"linch_protocol":1,"name":"Firefox","icon":"firefox","detail":"Web browser"}`
+        #[arg(long, conflicts_with_all = ["read0", "separator"])]
+        json: bool,
+
+        /// With --json, reject objects with fields this version doesn't know instead of ignoring them
+        #[arg(long, requires = "json")]
+        strict: bool,
+
+        /// Read the entries from this file instead of stdin. Faster for menus of millions of lines,
+        /// and split the same way so it may be combined with --read0
+        #[arg(long, value_name = "FILE")]
+        input_raw: Option<PathBuf>,
+
+        /// End the output with a NUL byte
+        #[arg(long, conflicts_with = "delimiter")]
+        print0: bool,
+
+        /// End the output with this text, such as $'\n'
+        #[arg(long)]
+        delimiter: Option<String>,
+
+        /// Copy the choice to the clipboard instead of printing it
+        #[arg(long)]
+        clipboard: bool,
+
+        /// Show icons for lines naming existing files or directories, by the type their extension suggests.
+        /// Other lines get no icon unless given one. Implies --icons
+        #[arg(long)]
+        file_icons: bool,
+    },
+    /// Evaluate arithmetic as it's typed, such as `2^10 / 3` or `sqrt(2) * pi`.
+    /// Enter copies the result to the clipboard and prints it
+    Calc,
+    /// Search emoji and symbols by name. Enter copies the chosen one to the clipboard and prints it
+    Emoji,
+    /// Hold stdin on the clipboard until something else is copied, for --clipboard and calc
+    #[command(hide = true)]
+    ServeClipboard,
+    /// Time discovery, caching, and matching against synthetic data
+    #[command(hide = true)]
+    Bench {
+        /// Desktop entries to generate
+        #[arg(long, default_value = "2000")]
+        entries: usize,
+
+        /// Binaries to generate
+        #[arg(long, default_value = "5000")]
+        binaries: usize,
+
+        /// dmenu lines to generate for sorting and matching
+        #[arg(long, default_value = "10000")]
+        lines: usize,
+
+        /// Print one JSON object instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage a cache by name, such as bin or app
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum CacheAction {
+    /// Curate a cache in the picker. Delete removes an entry, +/- adjust its count,
+    /// Ctrl+P toggles its pin. Enter saves all changes and Escape discards them
+    Edit { name: String },
+}
+
+impl LinchCmd {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Bin { .. } => "bin",
+            Self::App { .. } => "app",
+            Self::Dmenu { .. } => "dmenu",
+            Self::Calc => "calc",
+            Self::Emoji => "emoji",
+            Self::ServeClipboard => "serve-clipboard",
+            Self::Cache { .. } => "cache",
+            Self::Bench { .. } => "bench",
+        }
+    }
 }
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = "Exits with status 1 when closed without choosing anything, like dmenu")]
 struct LinchArgs {
     // {{{
     /// Which mode to run
     #[command(subcommand)]
-    command: LinchCmd,
+    command: Option<LinchCmd>,
+
+    /// Append successful bin and app launches to $XDG_STATE_HOME/linch/history.log
+    #[arg(long)]
+    history_log: bool,
+
+    /// Print the supported JSON protocol version and exit
+    #[arg(long)]
+    protocol_version: bool,
+
+    /// Print the version, build, and detected environment as JSON and exit
+    #[arg(long)]
+    version_json: bool,
 
+    /// Search hint. `{count}` shows the matches, `{total}` all items, `{mode}` the subcommand.
+    /// Write `{{` and `}}` for literal braces
     #[arg(short, long, default_value = "Run")]
     prompt: String,
 
@@ -832,13 +2496,27 @@ struct LinchArgs {
     #[arg(short, long, default_value = "15")]
     rows: NonZeroUsize,
 
-    /// Window width. Affected by scale
-    #[arg(short = 'x', long, default_value = "800.0")]
-    width: f32,
+    /// Lay out up to --columns items in a single row below the input, like a bar.
+    /// The window keeps the row height --height and --rows would give
+    #[arg(long)]
+    horizontal: bool,
+
+    /// Window width. Affected by scale, unless given as a percentage of the monitor such as 50%
+    #[arg(short = 'x', long, default_value = "800.0", value_parser = parse_length)]
+    width: Length,
+
+    /// Window height. Affected by scale, unless given as a percentage of the monitor such as 30%
+    #[arg(short = 'y', long, default_value = "400.0", value_parser = parse_length)]
+    height: Length,
 
-    /// Window height. Affected by scale
-    #[arg(short = 'y', long, default_value = "400.0")]
-    height: f32,
+    /// Open with the top left corner here instead of centered.
+    /// Wayland doesn't let windows place themselves, so there it's up to the compositor
+    #[arg(long, value_name = "X,Y", value_parser = parse_position)]
+    position: Option<[f32; 2]>,
+
+    /// Reopen wherever the window was last closed. --position still wins
+    #[arg(long)]
+    remember_position: bool,
 
     /// Foreground color in #hex or color space
     #[arg(short, long, default_value = "#ffffff", value_parser=parse_color)]
@@ -852,6 +2530,31 @@ struct LinchArgs {
     #[arg(short, long, default_value = "oklch 70% 60% 95", value_parser=parse_color)]
     accent: Color32,
 
+    /// Derive foreground and background from the accent color.
+    /// Overrides --foreground and --background
+    #[arg(long)]
+    palette_from_accent: bool,
+
+    /// Fill of the selected item. Defaults to the accent
+    #[arg(long, value_parser=parse_color)]
+    selection_bg: Option<Color32>,
+
+    /// Text of the selected item. Defaults to the background
+    #[arg(long, value_parser=parse_color)]
+    selection_fg: Option<Color32>,
+
+    /// Outline and text of the hovered item. Defaults to the accent
+    #[arg(long, value_parser=parse_color)]
+    hover_color: Option<Color32>,
+
+    /// Corner radius of the window and item frames in pixels
+    #[arg(long, default_value = "0.0")]
+    rounding: f32,
+
+    /// Width of the window outline in pixels. Input and hover outlines are two thirds of it
+    #[arg(long, default_value = "3.0")]
+    border_width: f32,
+
     /// Background opacity 0.0 -> 1.0
     #[arg(short, long, default_value = "0.8")]
     opacity: f32,
@@ -866,21 +2569,197 @@ struct LinchArgs {
     #[arg(short, long)]
     literal: bool,
 
+    /// Tell upper and lower case apart when searching
+    #[arg(long)]
+    case_sensitive: bool,
+
     /// Close linch on focus loss
     #[arg(short, long)]
     exit_unfocus: bool,
 
-    /// Override cache name.
-    /// If unset defaults to command name.
+    /// Arrow keys past the first or last item continue from the other end
+    #[arg(long)]
+    wrap: bool,
+
+    /// Override cache name. Counts are kept in $XDG_STATE_HOME/linch/cache_NAME.
+    /// If unset defaults to command name, except dmenu which only caches when this is set.
     /// If set to nothing "" caching isn't used
     #[arg(long)]
     cache: Option<String>,
 
+    /// Icon theme to search, followed by the themes it inherits and hicolor.
+    /// Defaults to `icon-theme` in the theme file, then the GTK icon theme
+    #[arg(long)]
+    icon_theme: Option<String>,
+
+    /// Show icons in bin and dmenu modes too.
+    /// Binaries use the icon of an app running them, else the themed icon of the same name,
+    /// and dmenu lines may name one after a tab as `name\ticon` or like rofi as `name\0icon\x1ficon`
+    #[arg(long, overrides_with = "no_icons")]
+    icons: bool,
+
+    /// Never show icons, including in app mode
+    #[arg(long)]
+    no_icons: bool,
+
+    /// Icon drawn for items whose own icon is missing or unresolved.
+    /// A built in one is used if no theme has it
+    #[arg(long, default_value = "application-x-executable")]
+    fallback_icon: String,
+
+    /// Leave the icon space blank for items without an icon
+    #[arg(long)]
+    no_fallback_icon: bool,
+
+    /// Show a detail column taking this fraction of each cell, such as 0.4.
+    /// Details are binary paths, application Exec lines,
+    /// or dmenu text after a tab as `name\tdetail`, following the icon if --icons is set.
+    /// Best with a single column
+    #[arg(long, value_parser = parse_fraction)]
+    detail_width: Option<f32>,
+
+    /// Also search the detail text, listing those matches last
+    #[arg(long)]
+    match_detail: bool,
+
+    /// Font file or fontconfig family name for all text, such as a CJK font for names the default lacks
+    #[arg(long, value_name = "PATH_OR_NAME")]
+    font: Option<String>,
+
+    /// Draw each application's comment in a second, smaller line below its name.
+    /// Items without one keep a single line
+    #[arg(long)]
+    descriptions: bool,
+
+    /// Show the item Enter would launch at the end of the input row, as `→ name`
+    #[arg(long)]
+    launch_hint: bool,
+
+    /// Icon size in pixels before --scale, defaulting to the row height.
+    /// The window grows taller if its rows are too short for them
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=1024))]
+    icon_size: Option<u32>,
+
+    /// Pick SVG icons over rasters of any size when a theme has both.
+    /// By default rasters win only when they are at least the icon size
+    #[arg(long, conflicts_with = "prefer_raster")]
+    prefer_svg: bool,
+
+    /// Use an app's -symbolic icon when it has no other, tinted to the text color.
+    /// Common with themes such as Adwaita that ship mostly symbolic icons
+    #[arg(long)]
+    symbolic_fallback: bool,
+
+    /// Pick raster icons over SVG when a theme has both, even if they must be scaled up
+    #[arg(long)]
+    prefer_raster: bool,
+
+    /// Keep regenerable caches such as rendered icons here instead of the XDG cache dir,
+    /// along with launch counts. Created when missing.
+    /// Without the flag the path in the `cache_dir` config file is used
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Look up and render every icon instead of reusing those cached by previous launches
+    #[arg(long)]
+    no_icon_cache: bool,
+
     /// Removes all cached entries for given cache
     #[arg(long)]
     clear_cache: bool,
+
+    /// Days after which a launch counts half as much towards the cache order. 0 never forgets
+    #[arg(long, value_name = "DAYS", default_value = "30")]
+    cache_halflife: f32,
+
+    /// Keep at most this many cache entries, forgetting the lowest scoring unpinned ones first
+    #[arg(long, value_name = "N")]
+    cache_max: Option<usize>,
+
+    /// Theme options given as flags
+    #[arg(skip)]
+    theme_fixed: Vec<&'static str>,
+} // }}}
+
+/// Entries of `data` split on newlines or NUL bytes, keeping their position in `line`.
+/// Every name is a slice of one copy of the whole input
+fn dmenu_items(data: &[u8], read0: bool, icons: bool, details: bool, separator: Option<&str>) -> Vec<Item> {
+    // {{{
+    let buf: Arc<str> = String::from_utf8_lossy(data).into();
+    buf.split(if read0 { '\0' } else { '\n' })
+        .enumerate()
+        .filter_map(|(n, e)| {
+            let e = if read0 { e } else { e.strip_suffix('\r').unwrap_or(e) };
+            (!e.trim().is_empty()).then_some((n, e))
+        })
+        .map(|(n, e)| {
+            let divider = separator.and_then(|m| Some((m, e.trim().strip_prefix(m)?)));
+            if let Some((marker, label)) = divider {
+                return Item {
+                    name: Name::within(&buf, label.trim_end_matches(marker).trim()),
+                    line: Some(n),
+                    separator: true,
+                    ..Default::default()
+                };
+            }
+            // rofi's `text\0icon\x1fname` options, always removed from the text
+            let (e, options) = e.split_once('\0').unwrap_or((e, ""));
+            let mut options = options.split('\x1f');
+            let rofi_icon = std::iter::from_fn(|| Some((options.next()?, options.next()?)))
+                .find_map(|(k, v)| (k == "icon" && !v.is_empty()).then(|| v.to_string()));
+            // tabs only separate the fields being shown, otherwise they're plain text
+            let mut fields = e.splitn(1 + icons as usize + details as usize, '\t');
+            let name = Name::within(&buf, fields.next().unwrap_or(&e[..0]));
+            let mut field = |shown: bool| {
+                shown
+                    .then(|| fields.next())
+                    .flatten()
+                    .filter(|f| !f.is_empty())
+                    .map(|f| f.to_string())
+            };
+            Item {
+                name,
+                icon: field(icons).or(rofi_icon.filter(|_| icons)),
+                detail: field(details),
+                line: Some(n),
+                ..Default::default()
+            }
+        })
+        .collect()
 } // }}}
 
+/// `--json` entries, one object per line keeping their position in `line`
+fn dmenu_json_items(data: &[u8], strict: bool, icons: bool, details: bool) -> Result<Vec<Item>, String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, l)| {
+            let entry: protocol::DmenuEntry =
+                protocol::from_json(l, strict).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+            Ok(Item {
+                name: entry.name.into(),
+                icon: entry.icon.filter(|_| icons),
+                detail: entry.detail.filter(|_| details),
+                line: Some(n),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Every icon name the configured theme chain has.
+/// Directory order doesn't matter when only collecting names
+fn theme_names(args: &LinchArgs) -> HashSet<String> {
+    let theme = args
+        .icon_theme
+        .clone()
+        .or_else(|| theme::get().icon_theme)
+        .or_else(icons::gtk_theme);
+    icons::Themes::load(theme.as_deref(), 0, 1, icons::Prefer::Auto).names()
+}
+
+#[allow(clippy::too_many_arguments)]
 fn response(
     items: Vec<Item>,
     custom: bool,
@@ -888,24 +2767,94 @@ fn response(
     args: LinchArgs,
     icons: bool,
     monochrome: bool,
+    sort: bool,
+    edit: bool,
 ) -> Option<Item> {
     // {{{
     let result: Arc<Mutex<Option<Item>>> = Arc::new(Mutex::new(None));
     let res_send = result.clone();
-    let scale = args.scale.unwrap_or(scale_factor());
-    if args.clear_cache {
-        remove_file(cache_file(&cache)).unwrap();
+    let icons = (icons || args.icons) && !args.no_icons;
+    let mode = args
+        .command
+        .as_ref()
+        .map(LinchCmd::name)
+        .unwrap_or_default()
+        .to_string();
+    let theme_base = theme::Theme {
+        foreground: Some(args.foreground),
+        background: Some(args.background),
+        accent: Some(args.accent),
+        opacity: Some(args.opacity),
+        scale: Some(args.scale.unwrap_or_else(|| scale_factor(env_var))),
+        icon_theme: args.icon_theme.clone(),
+    };
+    let look = theme::get().or(&theme_base, &args.theme_fixed);
+    let (opacity, scale) = (look.opacity.unwrap(), look.scale.unwrap());
+    let colors = palette(
+        look.foreground.unwrap(),
+        look.background.unwrap(),
+        look.accent.unwrap(),
+        args.palette_from_accent,
+    );
+    // before clearing so an old location can't come back afterwards
+    if !cache.is_empty() {
+        storage::migrate_counts(&cache)
+    }
+    if let Some(file) = (args.clear_cache && !cache.is_empty())
+        .then(|| storage::counts_file(&cache))
+        .flatten()
+    {
+        match remove_file(&file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                eprintln!("Could not clear cache {}\n{}", file.display(), e)
+            }
+            _ => (),
+        }
     }
+    let cache = if !cache.is_empty() && !cache_usable(&cache) {
+        String::new()
+    } else {
+        cache
+    };
+    let position = args
+        .position
+        .map(Pos2::from)
+        .or_else(|| args.remember_position.then(position_get).flatten());
+    // only app mode hides anything by default
+    let show_hidden = !matches!(args.command, Some(LinchCmd::App { all: false, .. }));
+    let (rows, height) = if args.horizontal {
+        (1, args.height.scaled(2.0 / (args.rows.get() + 1) as f32))
+    } else {
+        (args.rows.get(), args.height)
+    };
+    // percentages are resolved now where the work area can be found, otherwise once the window is open
+    let area = [args.width, height]
+        .iter()
+        .any(|l| l.fraction().is_some())
+        .then(monitor::work_area)
+        .flatten();
+    let resolve = |length: Length, n: usize| match (length, area) {
+        (Length::Fraction(f), Some(area)) => Length::Pixels(area[n] * f / scale),
+        _ => length,
+    };
+    let (width, height) = (resolve(args.width, 0), resolve(height, 1));
+    let size = [
+        width.or_pixels(800.0),
+        height.or_pixels(400.0).max(icon_height(args.icon_size, rows)),
+    ];
     eframe::run_native(
         "Linch",
         NativeOptions {
-            viewport: ViewportBuilder::default()
-                .with_decorations(false)
-                .with_inner_size((args.width * scale, args.height * scale))
-                .with_resizable(false)
-                .with_transparent(if args.opacity < 1.0 { true } else { false })
-                .with_window_level(WindowLevel::AlwaysOnTop),
-            centered: true,
+            viewport: ViewportBuilder {
+                position,
+                ..Default::default()
+            }
+            .with_decorations(false)
+            .with_inner_size((size[0] * scale, size[1] * scale))
+            .with_resizable(false)
+            .with_transparent(opacity < 1.0)
+            .with_window_level(WindowLevel::AlwaysOnTop),
+            centered: position.is_none(),
             ..Default::default()
         },
         Box::new(move |cc| {
@@ -916,18 +2865,50 @@ fn response(
                 custom,
                 cache,
                 args.prompt,
+                mode,
                 args.columns.into(),
-                args.rows.into(),
-                args.foreground,
-                args.background,
-                args.accent,
-                args.opacity,
+                rows,
+                colors,
+                opacity,
                 scale,
+                theme_base,
+                args.theme_fixed,
+                args.palette_from_accent,
+                [args.selection_bg, args.selection_fg],
+                args.hover_color,
+                args.rounding,
+                args.border_width,
                 args.literal,
+                args.case_sensitive,
                 args.exit_unfocus,
+                args.wrap,
                 icons,
                 monochrome,
-                [args.width, args.height],
+                look.icon_theme,
+                match (args.prefer_svg, args.prefer_raster) {
+                    (true, _) => icons::Prefer::Svg,
+                    (_, true) => icons::Prefer::Raster,
+                    _ => icons::Prefer::Auto,
+                },
+                args.symbolic_fallback,
+                sort,
+                edit,
+                show_hidden,
+                args.cache_halflife,
+                args.cache_max,
+                !args.no_icon_cache,
+                args.icon_size,
+                Some(args.fallback_icon).filter(|i| !i.is_empty() && !args.no_fallback_icon),
+                args.detail_width,
+                args.match_detail,
+                args.descriptions,
+                args.launch_hint,
+                matches!(args.command, Some(LinchCmd::Bin { args: true, .. })),
+                args.font,
+                size,
+                [width.fraction(), height.fraction()],
+                position.is_none(),
+                args.remember_position,
             )))
         }),
     )
@@ -937,40 +2918,198 @@ fn response(
     result
 } // }}}
 
+/// Icons of desktop entries by the program they run, so binaries can borrow them.
+/// Programs shared by entries with different icons, like flatpak, map to None
+fn app_icons() -> HashMap<String, Option<String>> {
+    let mut icons = HashMap::<String, Option<String>>::new();
+    for app in get_applications(true, &[], None, false) {
+        let (Some(exec), Some(icon)) = (app.exec.as_deref(), app.icon) else {
+            continue;
+        };
+        let Some(program) = exec
+            .split_whitespace()
+            .find(|w| *w != "env" && !w.contains('='))
+            .and_then(|p| p.rsplit('/').next())
+        else {
+            continue;
+        };
+        icons
+            .entry(program.to_string())
+            .and_modify(|kept| {
+                if kept.as_ref() != Some(&icon) {
+                    *kept = None
+                }
+            })
+            .or_insert(Some(icon));
+    }
+    icons
+}
+
+/// Print items in natural order for --list, as tab separated fields or JSON lines
+fn list_items(mut items: Vec<Item>, json: bool) {
+    items.sort_by(|a, b| natural_lexical_cmp(a.as_ref(), b.as_ref()));
+    for item in items {
+        let file = item.file.map(|f| f.to_string_lossy().to_string());
+        if json {
+            println!(
+                "{}",
+                protocol::to_json(protocol::ListedItem {
+                    name: item.name.to_string(),
+                    file,
+                    exec: item.exec,
+                    icon: item.icon,
+                    no_display: item.no_display,
+                })
+            );
+        } else {
+            // keep one item per line whatever the fields contain
+            let field = |s: Option<&str>| s.unwrap_or_default().replace(['\t', '\n'], " ");
+            println!(
+                "{}\t{}\t{}\t{}\t{}",
+                field(Some(&item.name)),
+                field(file.as_deref()),
+                field(item.exec.as_deref()),
+                field(item.icon.as_deref()),
+                item.no_display
+            );
+        }
+    }
+}
+
+/// Exit status when the window closes without a choice, so scripts can tell a cancel apart
+const CANCELLED: i32 = 1;
+
 fn main() {
     // {{{
-    let args = LinchArgs::parse();
-    match args.command {
-        LinchCmd::Bin => {
+    let matches = LinchArgs::command().get_matches();
+    let mut args = LinchArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.theme_fixed = theme::KEYS
+        .into_iter()
+        .filter(|k| matches.value_source(k) == Some(ValueSource::CommandLine))
+        .collect();
+    if args.protocol_version {
+        println!("{}", protocol::PROTOCOL_VERSION);
+        return;
+    }
+    theme::listen();
+    if let Some(dir) = args.cache_dir.clone().or_else(cache_dir_get) {
+        storage::set_cache_dir(dir)
+    }
+    if args.version_json {
+        println!("{}", protocol::to_json(version_report()));
+        return;
+    }
+    let Some(command) = args.command.clone() else {
+        LinchArgs::command()
+            .error(ErrorKind::MissingSubcommand, "A mode subcommand is required")
+            .exit()
+    };
+    let history = args.history_log;
+    match command {
+        LinchCmd::Bin {
+            only_listed,
+            show_missing,
+            monochrome,
+            list,
+            json,
+            args: _,
+        } => {
             #[cfg(debug_assertions)]
             let now = std::time::Instant::now();
-            let items = get_binaries();
+            let mut items = get_binaries();
+            if only_listed {
+                items = binlist_apply(&binlist_get(), items, show_missing);
+            }
+            if args.icons && !args.no_icons {
+                // an installed app's icon, else only names the theme has
+                // as looking up every binary would be far too slow
+                let names = theme_names(&args);
+                let apps = app_icons();
+                for item in items.iter_mut() {
+                    item.icon = apps
+                        .get(&*item.name)
+                        .cloned()
+                        .flatten()
+                        .or_else(|| names.contains(&*item.name).then(|| item.name.to_string()))
+                }
+            }
+            aliases_get().apply(&mut items);
+            if args.detail_width.is_some() {
+                for item in items.iter_mut() {
+                    item.detail = item.file.as_ref().map(|f| f.display().to_string())
+                }
+            }
             #[cfg(debug_assertions)]
             println!("{} items found in {:?}", items.len(), now.elapsed());
+            if list {
+                return list_items(items, json);
+            }
             if let Some(item) = response(
                 items,
                 false,
                 args.cache.clone().unwrap_or(String::from("bin")),
                 args,
                 false,
+                monochrome,
+                !only_listed,
                 false,
             ) {
-                let mut command = std::process::Command::new(item.as_ref());
+                // name may be an alias rename, so the real path is used.
+                // only --show-missing placeholders have none
+                let Some(file) = item.file.as_ref() else {
+                    eprintln!("{} is listed but not installed", item);
+                    std::process::exit(1)
+                };
+                let mut command = std::process::Command::new(file);
+                command.args(item.exec.as_deref().map(split_arguments).unwrap_or_default());
                 if let Err(e) = command.spawn() {
-                    panic!(
-                        "Could not start process {}\n{}",
-                        command.get_program().to_string_lossy(),
-                        e
-                    );
+                    eprintln!("Could not start process {}\n{}", file.display(), e);
+                    std::process::exit(1)
                 };
+                if history {
+                    history_log("bin", &item, &command)
+                }
+            } else {
+                std::process::exit(CANCELLED)
             }
         }
-        LinchCmd::App { all, monochrome } => {
+        LinchCmd::App {
+            all,
+            monochrome,
+            categories,
+            no_dbus_activation,
+            merge_by_exec: merge,
+            merge_ignore_args,
+            gpu_env,
+            mime,
+            files,
+            verbose,
+            list,
+            json,
+        } => {
+            // entries may run elsewhere through Path
+            let files: Vec<PathBuf> = files.into_iter().filter_map(|f| std::path::absolute(f).ok()).collect();
             #[cfg(debug_assertions)]
             let now = std::time::Instant::now();
-            let items = get_applications(all);
+            // hidden entries are loaded anyway for Ctrl+H
+            let mut items = get_applications(all || !list, &categories, mime.as_deref(), verbose);
+            if merge {
+                items = merge_by_exec(items, merge_ignore_args);
+            }
+            if args.detail_width.is_some() {
+                let detail = |i: &Item| i.exec.clone().map(|e| if i.prefers_dgpu { e + " (dGPU)" } else { e });
+                for item in items.iter_mut() {
+                    item.detail = detail(item);
+                    for action in item.actions.iter_mut() {
+                        action.detail = detail(action)
+                    }
+                }
+            }
             #[cfg(debug_assertions)]
             println!("{} items found in {:?}", items.len(), now.elapsed());
+            if list {
+                return list_items(items, json);
+            }
             if let Some(item) = response(
                 items,
                 false,
@@ -978,73 +3117,870 @@ fn main() {
                 args,
                 true,
                 monochrome,
+                true,
+                false,
             ) {
-                let file = item.file.unwrap();
-                for launcher in [
-                    std::process::Command::new("dex").arg(&file),
-                    std::process::Command::new("gio").arg("launch").arg(&file),
-                    std::process::Command::new("exo-open").arg(&file),
-                ] {
-                    if launcher.spawn().is_ok() {
+                if let Some(url) = item.url.as_ref() {
+                    for opener in [
+                        std::process::Command::new("xdg-open").arg(url),
+                        std::process::Command::new("gio").arg("open").arg(url),
+                    ] {
+                        if opener.spawn().is_ok() {
+                            if history {
+                                history_log("app", &item, opener)
+                            }
+                            return;
+                        }
+                    }
+                    eprintln!("Could not open {} with xdg-open or gio", url);
+                    return;
+                }
+                // single instance apps expect this instead of a second process
+                let id = launch_id(&item);
+                if item.dbus_activatable && !no_dbus_activation {
+                    let mut activation = dbus_activation(&id, item.action.as_deref(), &files);
+                    if activation.status().is_ok_and(|s| s.success()) {
+                        if history {
+                            history_log("app", &item, &activation)
+                        }
                         return;
                     }
+                    eprintln!("D-Bus activation of {} failed. Falling back to launchers", id);
                 }
-                eprintln!("All featured launchers failed. Falling back to gtk-launch");
-                match std::process::Command::new("gtk-launch")
-                    .arg(file.file_stem().unwrap())
-                    .spawn()
-                {
-                    Ok(mut child) => {
-                        if child.wait().unwrap().success() {
+                // the featured launchers can't start actions, so those always use Exec directly
+                if item.action.is_none() {
+                    let file = item.file.clone().unwrap();
+                    for launcher in [
+                        std::process::Command::new("dex").arg(&file),
+                        std::process::Command::new("gio").arg("launch").arg(&file).args(&files),
+                        std::process::Command::new("exo-open").arg(&file),
+                    ] {
+                        // only gio passes files along
+                        if (files.is_empty() || launcher.get_program() == "gio") && launcher.spawn().is_ok() {
+                            if history {
+                                history_log("app", &item, launcher)
+                            }
                             return;
                         }
                     }
-                    Err(e) => eprintln!("{}", e),
+                    eprintln!("All featured launchers failed. Falling back to gtk-launch");
+                    let mut gtk_launch = std::process::Command::new("gtk-launch");
+                    gtk_launch.arg(&id).args(&files);
+                    match gtk_launch.spawn() {
+                        Ok(mut child) => {
+                            if child.wait().unwrap().success() {
+                                if history {
+                                    history_log("app", &item, &gtk_launch)
+                                }
+                                return;
+                            }
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                    eprintln!("Falling back to manual desktop entry launching");
                 }
-                eprintln!("Falling back to manual desktop entry launching");
                 if let Some(exec) = item.exec.as_ref() {
-                    let items = exec.split_whitespace().collect::<Vec<&str>>();
-                    let mut command = if let Some(mut path) = item.path.clone() {
-                        path.push(items[0]);
-                        std::process::Command::new(path)
+                    let items = expand_exec(exec, &item, &files);
+                    let Some((program, items)) = items.split_first() else {
+                        eprintln!("Entry has an empty Exec");
+                        return;
+                    };
+                    // spawn would only report a missing file, not which
+                    if let Some(path) = item.path.as_ref().filter(|p| !p.is_dir()) {
+                        eprintln!("Entry Path {} is not a directory", path.display());
+                        std::process::exit(1)
+                    }
+                    // older entries name a program inside Path, so keep preferring that
+                    let program = match item.path.as_ref().map(|p| p.join(program)) {
+                        Some(joined) if joined.is_file() => joined.into_os_string(),
+                        _ => OsString::from(program),
+                    };
+                    let terminal = if item.terminal {
+                        let terminal = terminal_emulator();
+                        if terminal.is_none() {
+                            eprintln!("Entry requests a terminal but none was found. Set TERMINAL to fix");
+                        }
+                        terminal
                     } else {
-                        std::process::Command::new(items[0])
+                        None
                     };
-                    if let Some(args) = items.get(1..) {
-                        command.args(args);
+                    let mut command = if let Some(terminal) = terminal {
+                        let mut command = std::process::Command::new(terminal);
+                        command.arg("-e").arg(program);
+                        command
+                    } else {
+                        std::process::Command::new(program)
+                    };
+                    command.args(items);
+                    if let Some(path) = item.path.as_ref() {
+                        command.current_dir(path);
                     }
-                    if let Err(err_exec) = command.spawn() {
-                        eprintln!("Starting application directly failed: {}", err_exec);
+                    // launchers apply this themselves
+                    if item.prefers_dgpu {
+                        command.envs(if gpu_env.is_empty() { dgpu_env() } else { gpu_env });
+                    }
+                    match command.spawn() {
+                        Ok(_) => {
+                            if history {
+                                history_log("app", &item, &command)
+                            }
+                        }
+                        Err(err_exec) => eprintln!("Starting application directly failed: {}", err_exec),
                     }
                 }
+            } else {
+                std::process::exit(CANCELLED)
             }
         }
-        LinchCmd::Dmenu => {
-            let items: Vec<Item> = std::io::stdin()
-                .lines()
-                .filter_map(|r| match r.ok() {
-                    Some(l) => {
-                        if l.trim().is_empty() {
-                            None
-                        } else {
-                            Some(Item {
-                                name: l,
-                                file: None,
-                                exec: None,
-                                path: None,
-                                icon: None,
-                                hidden: false,
-                            })
-                        }
-                    }
-                    None => None,
+        LinchCmd::Dmenu {
+            index,
+            separator,
+            read0,
+            json,
+            strict,
+            input_raw,
+            print0,
+            delimiter,
+            clipboard,
+            file_icons,
+        } => {
+            let icons = args.icons && !args.no_icons;
+            let details = args.detail_width.is_some();
+            let data = match input_raw {
+                Some(file) => std::fs::read(&file).unwrap_or_else(|e| {
+                    eprintln!("Could not read {}\n{}", file.display(), e);
+                    std::process::exit(1)
+                }),
+                None => {
+                    let mut data = Vec::new();
+                    std::io::stdin().read_to_end(&mut data).ok();
+                    data
+                }
+            };
+            let mut items = if json {
+                dmenu_json_items(&data, strict, icons, details).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1)
                 })
-                .collect();
+            } else {
+                dmenu_items(&data, read0, icons, details, separator.as_deref())
+            };
+            if file_icons && !args.no_icons {
+                let names = theme_names(&args);
+                for item in items.iter_mut().filter(|i| i.icon.is_none() && !i.separator) {
+                    item.icon = icons::file_icon(Path::new(&*item.name), &names)
+                }
+            }
 
             let custom = items.is_empty();
-            if let Some(item) = response(items, custom, "".to_string(), args, false, false) {
-                print!("{}", item);
+            let sort = separator.is_none();
+            // only cached when asked for, as every script's menu would otherwise share one
+            let cache = args.cache.clone().unwrap_or_default();
+            let cache = if !sort && !cache.is_empty() {
+                eprintln!("--cache is ignored with --separator, which keeps the input order");
+                String::new()
+            } else {
+                cache
+            };
+            if let Some(item) = response(items, custom, cache, args, file_icons, false, sort, false) {
+                let end = if print0 {
+                    String::from("\0")
+                } else {
+                    delimiter.unwrap_or_default()
+                };
+                if clipboard {
+                    clipboard_set(&item.name)
+                } else if index {
+                    print!("{}{}", item.line.map_or(-1, |n| n as isize), end);
+                } else {
+                    print!("{}{}", item, end);
+                }
+            } else {
+                std::process::exit(CANCELLED)
             }
         }
+        LinchCmd::Calc => {
+            if let Some(item) = response(Vec::new(), false, String::new(), args, false, false, false, false) {
+                clipboard_set(&item.name);
+                println!("{}", item)
+            } else {
+                std::process::exit(CANCELLED)
+            }
+        }
+        LinchCmd::Emoji => {
+            // the table's order keeps related symbols together
+            let cache = args.cache.clone().unwrap_or(String::from("emoji"));
+            if let Some(item) = response(emoji::items(), false, cache, args, false, false, false, false) {
+                clipboard_set(emoji::glyph(&item));
+                println!("{}", emoji::glyph(&item))
+            } else {
+                std::process::exit(CANCELLED)
+            }
+        }
+        LinchCmd::ServeClipboard => clipboard_serve(),
+        LinchCmd::Bench {
+            entries,
+            binaries,
+            lines,
+            json,
+        } => bench::run(entries, binaries, lines, json),
+        LinchCmd::Cache {
+            action: CacheAction::Edit { name },
+        } => {
+            if !name.is_empty() {
+                storage::migrate_counts(&name)
+            }
+            if name.is_empty() || !cache_usable(&name) {
+                eprintln!("Cannot edit cache \"{}\"", name);
+                std::process::exit(1)
+            }
+            let items = cache_get(&name)
+                .into_iter()
+                .map(|e| Item {
+                    badge: Some(e.badge()),
+                    name: e.name.into(),
+                    ..Default::default()
+                })
+                .collect();
+            response(items, false, name, args, false, false, true, true);
+        }
     };
 } // }}}
+
+#[cfg(test)]
+mod tests {
+    // {{{
+    use super::*;
+
+    fn menu(names: &[&str], rows: usize, columns: usize) -> Linch {
+        let items = names
+            .iter()
+            .map(|n| Item {
+                name: (*n).into(),
+                ..Default::default()
+            })
+            .collect();
+        Linch::headless(items, Arc::default(), rows, columns)
+    }
+
+    fn key(linch: &mut Linch, arrow: Command) -> Action {
+        linch.handle(FrameInput {
+            arrow: Some(arrow),
+            ..Default::default()
+        })
+    }
+
+    fn command(linch: &mut Linch, command: Command) -> Action {
+        linch.handle(FrameInput {
+            command: Some(command),
+            ..Default::default()
+        })
+    }
+
+    fn typed(linch: &mut Linch, text: &str) {
+        linch.handle(FrameInput {
+            text: Some(text.to_string()),
+            ..Default::default()
+        });
+    }
+
+    fn selected(linch: &Linch) -> Option<String> {
+        linch.selected().map(|i| i.name.to_string())
+    }
+
+    #[test]
+    fn type_down_enter() {
+        let mut linch = menu(&["files", "firefox", "fish", "firewall", "gimp"], 4, 1);
+        typed(&mut linch, "fir");
+        assert_eq!(linch.items_filter().count(), 2);
+        assert_eq!(selected(&linch).as_deref(), Some("firefox"));
+        key(&mut linch, Command::Down);
+        assert_eq!(selected(&linch).as_deref(), Some("firewall"));
+        assert!(command(&mut linch, Command::Submit) == Action::Submit);
+        linch.set();
+        let response = linch.response.lock().unwrap().take();
+        assert_eq!(response.map(|i| i.name.to_string()).as_deref(), Some("firewall"));
+    }
+
+    #[test]
+    fn clearing_restores_selection() {
+        let mut linch = menu(&["apple", "banana", "cherry", "date", "elder"], 2, 1);
+        for _ in 0..3 {
+            key(&mut linch, Command::Down);
+        }
+        assert_eq!((linch.scroll, linch.index), (1, 1));
+        typed(&mut linch, "e");
+        key(&mut linch, Command::Down);
+        typed(&mut linch, "");
+        assert_eq!(selected(&linch).as_deref(), Some("date"));
+        assert_eq!((linch.scroll, linch.index), (1, 1));
+    }
+
+    #[test]
+    fn restores_item_not_slot() {
+        // the new filter puts cherry where banana was
+        let mut linch = menu(&["apple", "banana", "cherry"], 3, 1);
+        key(&mut linch, Command::Down);
+        typed(&mut linch, "e");
+        typed(&mut linch, "");
+        assert_eq!(selected(&linch).as_deref(), Some("banana"));
+    }
+
+    #[test]
+    fn restore_after_rescan() {
+        // an earlier item went away, so the selection moved up a slot
+        let mut linch = menu(&["apple", "banana", "cherry", "date"], 4, 1);
+        key(&mut linch, Command::Down);
+        key(&mut linch, Command::Down);
+        typed(&mut linch, "a");
+        linch.items.remove(0);
+        typed(&mut linch, "");
+        assert_eq!(selected(&linch).as_deref(), Some("cherry"));
+
+        // the selection itself went away, so the same slot is used within the new list
+        let mut linch = menu(&["apple", "banana", "cherry"], 4, 1);
+        key(&mut linch, Command::Down);
+        key(&mut linch, Command::Down);
+        typed(&mut linch, "a");
+        linch.items.pop();
+        typed(&mut linch, "");
+        assert_eq!(selected(&linch).as_deref(), Some("banana"));
+    }
+
+    #[test]
+    fn legacy_cache_lines_decay() {
+        let (year, now) = (365 * 86400, 1_700_000_000);
+        let entries = cache_parse(&format!("40 old\n3@{} new\n", now), Some(now - year));
+        let old = entries.iter().find(|e| e.name == "old").unwrap();
+        let new = entries.iter().find(|e| e.name == "new").unwrap();
+        assert_eq!(old.last, Some(now - year));
+        assert_eq!(new.last, Some(now));
+        // a year of 30 day halflives leaves nothing of 40 launches
+        assert!(old.score(now, 30.0) < new.score(now, 30.0));
+        assert_eq!(cache_parse("40 old", None)[0].score(now, 30.0), 40.0);
+    }
+
+    #[test]
+    fn exec_uris() {
+        let item = Item::default();
+        let files = [PathBuf::from("/tmp/a b.pdf"), PathBuf::from("/tmp/c.pdf")];
+        assert_eq!(
+            expand_exec("viewer %u", &item, &files),
+            ["viewer", "file:///tmp/a%20b.pdf"]
+        );
+        assert_eq!(
+            expand_exec("viewer %U", &item, &files),
+            ["viewer", "file:///tmp/a%20b.pdf", "file:///tmp/c.pdf"]
+        );
+        assert_eq!(
+            expand_exec("viewer %F", &item, &files),
+            ["viewer", "/tmp/a b.pdf", "/tmp/c.pdf"]
+        );
+        assert_eq!(
+            expand_exec("viewer --file=%f", &item, &files),
+            ["viewer", "--file=/tmp/a b.pdf"]
+        );
+    }
+
+    #[test]
+    fn dmenu_lossy_shared() {
+        let items = dmenu_items(b"one\r\ntw\xffo\n\nthree\tcomment", false, false, true, None);
+        let names: Vec<&str> = items.iter().map(|i| &*i.name).collect();
+        assert_eq!(names, ["one", "tw\u{FFFD}o", "three"]);
+        assert_eq!(
+            items.iter().map(|i| i.line).collect::<Vec<_>>(),
+            [Some(0), Some(1), Some(3)]
+        );
+        assert_eq!(items[2].detail.as_deref(), Some("comment"));
+        // one backing buffer for every name
+        let span = items[2].name.as_ptr() as usize - items[0].name.as_ptr() as usize;
+        assert_eq!(span, "one\r\ntw\u{FFFD}o\n\n".len());
+    }
+
+    /// Names of the items selected after each arrow
+    fn walk(linch: &mut Linch, arrows: &[Command]) -> Vec<String> {
+        arrows
+            .iter()
+            .map(|a| {
+                key(linch, *a);
+                selected(linch).unwrap()
+            })
+            .collect()
+    }
+
+    const ITEMS: [&str; 5] = ["a", "b", "c", "d", "e"];
+
+    #[test]
+    fn single_row() {
+        use Command::*;
+        let mut linch = menu(&ITEMS, 1, 2);
+        assert_eq!(
+            walk(&mut linch, &[Down, Down, Down, Down, Down]),
+            ["b", "c", "d", "e", "e"]
+        );
+        assert_eq!(walk(&mut linch, &[Up, Up, Up, Up, Up]), ["d", "c", "b", "a", "a"]);
+        assert_eq!(
+            walk(&mut linch, &[Right, Right, Right, Right, Right]),
+            ["b", "c", "d", "e", "e"]
+        );
+        assert_eq!(
+            walk(&mut linch, &[Left, Left, Left, Left, Left]),
+            ["d", "c", "b", "a", "a"]
+        );
+        let mut linch = menu(&ITEMS[..1], 1, 3);
+        assert_eq!(walk(&mut linch, &[Down, Up, Right, Left]), ["a", "a", "a", "a"]);
+    }
+
+    #[test]
+    fn single_column() {
+        use Command::*;
+        let mut linch = menu(&ITEMS, 2, 1);
+        assert_eq!(
+            walk(&mut linch, &[Down, Down, Down, Down, Down]),
+            ["b", "c", "d", "e", "e"]
+        );
+        assert_eq!(walk(&mut linch, &[Up, Up, Up, Up, Up]), ["d", "c", "b", "a", "a"]);
+        // a page at a time, keeping the row while there's an item in it
+        assert_eq!(
+            walk(&mut linch, &[Right, Right, Right, Left, Left]),
+            ["c", "e", "e", "c", "a"]
+        );
+        key(&mut linch, Down);
+        assert_eq!(walk(&mut linch, &[Right, Right, Left]), ["d", "e", "c"]);
+    }
+
+    #[test]
+    fn single_cell() {
+        use Command::*;
+        for len in [1, 3] {
+            let mut linch = menu(&ITEMS[..len], 1, 1);
+            let last = ITEMS[len - 1];
+            assert_eq!(walk(&mut linch, &[Down, Down, Down])[2], last);
+            assert_eq!(walk(&mut linch, &[Up, Up, Up])[2], "a");
+            assert_eq!(walk(&mut linch, &[Right, Right, Right])[2], last);
+            assert_eq!(walk(&mut linch, &[Left, Left, Left])[2], "a");
+        }
+    }
+
+    #[test]
+    fn prune_keeps_launched() {
+        let now = 1_700_000_000;
+        let entry = |name: &str, count, pinned| CacheEntry {
+            count,
+            pinned,
+            last: Some(now),
+            name: name.to_string(),
+        };
+        let cache = || {
+            vec![
+                entry("pin", 1, true),
+                entry("often", 9, false),
+                entry("new", 1, false),
+                entry("rare", 2, false),
+            ]
+        };
+        let names = |cache: Vec<CacheEntry>| cache.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names(cache_prune(cache(), "new", now, 0.0, 3)), ["pin", "new", "often"]);
+        assert_eq!(names(cache_prune(cache(), "new", now, 0.0, 1)), ["pin", "new"]);
+        assert_eq!(names(cache_prune(cache(), "new", now, 0.0, 0)), ["pin", "new"]);
+        assert_eq!(names(cache_prune(cache(), "often", now, 0.0, 2)), ["pin", "often"]);
+    }
+
+    fn vars(pairs: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        |name| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn detect_session() {
+        assert_eq!(session_type(vars(&[])), None);
+        assert_eq!(session_type(vars(&[("DISPLAY", ":0")])).as_deref(), Some("x11"));
+        assert_eq!(
+            session_type(vars(&[("DISPLAY", ":0"), ("WAYLAND_DISPLAY", "wayland-1")])).as_deref(),
+            Some("wayland")
+        );
+        assert_eq!(
+            session_type(vars(&[("XDG_SESSION_TYPE", "tty"), ("DISPLAY", ":0")])).as_deref(),
+            Some("tty")
+        );
+        assert_eq!(
+            session_type(vars(&[("XDG_SESSION_TYPE", ""), ("DISPLAY", ":0")])).as_deref(),
+            Some("x11")
+        );
+    }
+
+    #[test]
+    fn detect_scale() {
+        assert_eq!(scale_factor(vars(&[])), 1.0);
+        assert_eq!(scale_factor(vars(&[("GDK_SCALE", "2")])), 2.0);
+        assert_eq!(scale_factor(vars(&[("GDK_DPI_SCALE", "1.5"), ("GDK_SCALE", "2")])), 1.5);
+        // bad values are skipped rather than fatal
+        assert_eq!(scale_factor(vars(&[("GDK_DPI_SCALE", "big"), ("GDK_SCALE", "2")])), 2.0);
+        assert_eq!(scale_factor(vars(&[("GDK_DPI_SCALE", "-1")])), 1.0);
+        assert_eq!(scale_factor(vars(&[("GDK_SCALE", "NaN")])), 1.0);
+    }
+
+    fn history_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("linch-history-{}-{}", test, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[test]
+    fn history_rotates() {
+        let dir = history_dir("rotate");
+        for n in 0..10 {
+            history_append(&dir, &format!("line {}\n", n), 20).unwrap();
+        }
+        let current = read_to_string(dir.join("history.log")).unwrap();
+        let rotated = read_to_string(dir.join("history.log.1")).unwrap();
+        assert_eq!(current, "line 9\n");
+        assert_eq!(rotated, "line 6\nline 7\nline 8\n");
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn history_concurrent() {
+        let dir = history_dir("concurrent");
+        let line = |t: usize, n: usize| format!("{}\t{:03}\t{}\n", t, n, "x".repeat(100));
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let (dir, line) = (&dir, &line);
+                s.spawn(move || {
+                    for n in 0..50 {
+                        history_append(dir, &line(t, n), 2000).unwrap()
+                    }
+                });
+            }
+        });
+        let read = |f: &str| read_to_string(dir.join(f)).unwrap();
+        let (rotated, current) = (read("history.log.1"), read("history.log"));
+        // taking turns makes it the same as appending one after another,
+        // so with 109 byte lines each file moves at 19 and the 400th starts a new one
+        let whole = |data: &str| data.lines().all(|l| l.len() + 1 == line(0, 0).len());
+        assert!(whole(&rotated) && whole(&current));
+        assert_eq!((rotated.lines().count(), current.lines().count()), (19, 1));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn dbus_ids() {
+        let item = Item {
+            file: Some(PathBuf::from("/usr/share/applications/kde4/org.kde.foo.desktop")),
+            id: Some(String::from("kde4-org.kde.foo.desktop")),
+            ..Default::default()
+        };
+        assert_eq!(launch_id(&item), "kde4-org.kde.foo");
+        let file_only = Item { id: None, ..item };
+        assert_eq!(launch_id(&file_only), "org.kde.foo");
+        let activation = dbus_activation("org.kde.foo", None, &[]);
+        let args: Vec<_> = activation.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(
+            args,
+            [
+                "call",
+                "--session",
+                "--dest",
+                "org.kde.foo",
+                "--object-path",
+                "/org/kde/foo",
+                "--method",
+                "org.freedesktop.Application.Activate",
+                "{}"
+            ]
+        );
+    }
+
+    #[test]
+    fn back_closes() {
+        let mut linch = menu(&["a"], 4, 1);
+        assert!(command(&mut linch, Command::Back) == Action::Close);
+    }
+
+    #[test]
+    fn custom_input() {
+        let mut linch = menu(&[], 4, 1);
+        linch.custom = true;
+        typed(&mut linch, "anything");
+        linch.set();
+        let response = linch.response.lock().unwrap().take();
+        assert_eq!(response.map(|i| i.name.to_string()).as_deref(), Some("anything"));
+    }
+
+    #[test]
+    fn aliases_conflicts() {
+        let aliases = aliases_parse(
+            "# comment\n\nimage viewer = gwenview\nphotos = gwenview\nphotos = gwenview\nviewer = eog\n\
+             gwenview -> Gwenview\ngwenview -> Image Viewer (Gwenview)\nno separator\n = gwenview\n",
+        );
+        assert_eq!(aliases.keywords["gwenview"], ["image viewer", "photos"]);
+        assert_eq!(aliases.keywords["eog"], ["viewer"]);
+        assert_eq!(aliases.keywords.len(), 2);
+        // the last rename wins
+        assert_eq!(aliases.renames["gwenview"], "Image Viewer (Gwenview)");
+        assert_eq!(aliases.renames.len(), 1);
+    }
+
+    #[test]
+    fn aliases_search_and_rename() {
+        let mut items: Vec<Item> = ["gwenview", "firefox"]
+            .iter()
+            .map(|n| Item {
+                name: (*n).into(),
+                keywords: vec![String::from("image")],
+                ..Default::default()
+            })
+            .collect();
+        items[1].keywords.clear();
+        aliases_parse("picture = gwenview\ngwenview -> Image Viewer (Gwenview)\nbrowser = firefox\n").apply(&mut items);
+        // added keywords keep the entry's own
+        assert_eq!(items[0].keywords, ["image", "picture"]);
+        let mut linch = Linch::headless(items, Arc::default(), 4, 1);
+        typed(&mut linch, "pict");
+        assert_eq!(selected(&linch).as_deref(), Some("Image Viewer (Gwenview)"));
+        typed(&mut linch, "brow");
+        assert_eq!(selected(&linch).as_deref(), Some("firefox"));
+    }
+
+    #[test]
+    fn exec_quoting() {
+        let args = |exec: &str| tokenize_exec(exec).into_iter().map(|(a, _)| a).collect::<Vec<_>>();
+        assert_eq!(
+            args(r#"env WINEPREFIX="/home/me/My Games" wine start /unix "C:\\\\Games\\\\x.exe""#),
+            [
+                "env",
+                "WINEPREFIX=/home/me/My Games",
+                "wine",
+                "start",
+                "/unix",
+                r"C:\Games\x.exe"
+            ]
+        );
+        assert_eq!(
+            args(r#"sh -c "echo \\"hi\\" \\$HOME \\`date\\`""#),
+            ["sh", "-c", r#"echo "hi" $HOME `date`"#]
+        );
+        // empty quotes are still an argument, runs of whitespace aren't
+        assert_eq!(args("app  \"\"\t--flag"), ["app", "", "--flag"]);
+        // string escapes apply before splitting, so \s separates like a space
+        assert_eq!(args(r"my\sapp --x"), ["my", "app", "--x"]);
+        assert_eq!(
+            tokenize_exec(r#"app "%f" %f"#),
+            [
+                (String::from("app"), false),
+                (String::from("%f"), true),
+                (String::from("%f"), false)
+            ]
+        );
+        let files = [PathBuf::from("/tmp/a b")];
+        assert_eq!(
+            expand_exec(r#""/opt/My App/run" "%f" %f"#, &Item::default(), &files),
+            ["/opt/My App/run", "%f", "/tmp/a b"]
+        );
+    }
+
+    #[test]
+    fn cache_sort_matches_comparator() {
+        // the plain comparator, looking the cache up on every comparison
+        fn reference(entries: &[CacheEntry], items: &mut [Item], lexical: bool) {
+            let key = |i: &Item| {
+                entries
+                    .iter()
+                    .find(|e| e.name == *i.name)
+                    .map_or((false, 0.0), |e| (e.pinned, e.score(0, 30.0)))
+            };
+            items.sort_by(|a, b| {
+                let ((pa, sa), (pb, sb)) = (key(a), key(b));
+                let ord = pa.cmp(&pb).then(sa.total_cmp(&sb)).reverse();
+                if lexical {
+                    ord.then_with(|| natural_lexical_cmp(a.as_ref(), b.as_ref()))
+                } else {
+                    ord
+                }
+            });
+            rank_sort(items)
+        }
+        let mut seed = 0x2545_f491_u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let parts = ["app", "App", "app2", "app10", "Écran", "zed", "_x", "9", "ß", "Foo Bar"];
+        let names: Vec<String> = (0..400)
+            .map(|_| format!("{}{}", parts[next(10) as usize], parts[next(10) as usize]))
+            .collect();
+        // dated to the file, so scores are the plain counts and many tie
+        let mut entries = Vec::new();
+        for name in names.iter().collect::<std::collections::BTreeSet<_>>() {
+            if next(2) == 0 {
+                continue;
+            }
+            entries.push(CacheEntry {
+                count: next(4) as usize,
+                pinned: next(8) == 0,
+                last: None,
+                name: name.clone(),
+            })
+        }
+        let items: Vec<Item> = names
+            .iter()
+            .map(|name| Item {
+                name: name.as_str().into(),
+                rank: (next(20) == 0).then(|| next(3) as usize),
+                ..Default::default()
+            })
+            .collect();
+        for lexical in [true, false] {
+            let (mut sorted, mut expected) = (items.clone(), items.clone());
+            cache_sort(entries.clone(), &mut sorted, lexical, 30.0);
+            reference(&entries, &mut expected, lexical);
+            assert!(sorted == expected, "lexical {}", lexical);
+        }
+    }
+
+    #[test]
+    fn tombstones_get_no_texture() {
+        let color_images = HashMap::from([
+            (
+                String::from("good"),
+                (PathBuf::from("/good.png"), Some(ColorImage::new([1, 1], Color32::RED))),
+            ),
+            (String::from("bad"), (PathBuf::from("/bad.svg"), None)),
+        ]);
+        let (images, sources) = load_textures(&Context::default(), color_images);
+        assert!(images.contains_key("good") && !images.contains_key("bad"));
+        assert_eq!(sources.keys().collect::<Vec<_>>(), ["good"]);
+    }
+
+    #[test]
+    fn exec_normalization() {
+        let key = |exec| normalized_exec(exec, false).unwrap();
+        assert_eq!(key("env GDK_BACKEND=wayland /usr/bin/app %U"), ["app"]);
+        assert_eq!(key("env GDK_BACKEND=x11 app %U"), ["app"]);
+        assert_eq!(key("app --safe-mode %u"), ["app", "--safe-mode"]);
+        // quoted codes are literal arguments, and other words are kept as written
+        assert_eq!(key(r#"app "%u" 100%%"#), ["app", "%u", "100%%"]);
+        assert_eq!(normalized_exec("app --safe-mode %u", true).unwrap(), ["app"]);
+        assert_eq!(normalized_exec("env FOO=1 %f", false), None);
+    }
+
+    #[test]
+    fn merged_variants() {
+        let entry = |name: &str, exec: Option<&str>, no_display| Item {
+            name: name.into(),
+            exec: exec.map(String::from),
+            no_display,
+            ..Default::default()
+        };
+        let items = || {
+            vec![
+                entry("App (Wayland)", Some("env GDK_BACKEND=wayland app %U"), false),
+                entry("App (X11)", Some("env GDK_BACKEND=x11 /usr/bin/app %U"), false),
+                entry("App", Some("app %U"), true),
+                entry("App (Safe Mode)", Some("app --safe-mode"), false),
+                entry("Docs", None, false),
+            ]
+        };
+        let summary = |items: Vec<Item>| {
+            let mut summary: Vec<(String, Vec<String>)> = items
+                .into_iter()
+                .map(|i| {
+                    (
+                        i.name.to_string(),
+                        i.actions.iter().map(|a| a.name.to_string()).collect(),
+                    )
+                })
+                .collect();
+            summary.sort();
+            summary
+        };
+        // the shortest name that isn't NoDisplay is shown, with the others as its actions
+        assert_eq!(
+            summary(merge_by_exec(items(), false)),
+            [
+                (String::from("App (Safe Mode)"), vec![]),
+                (
+                    String::from("App (X11)"),
+                    vec![String::from("App (Wayland)"), String::from("App")]
+                ),
+                (String::from("Docs"), vec![]),
+            ]
+        );
+        assert_eq!(
+            summary(merge_by_exec(items(), true)),
+            [
+                (
+                    String::from("App (X11)"),
+                    vec![
+                        String::from("App (Wayland)"),
+                        String::from("App (Safe Mode)"),
+                        String::from("App")
+                    ]
+                ),
+                (String::from("Docs"), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn wrap_around() {
+        // two full pages of two columns, each opening with a divider
+        let mut linch = menu(&["--", "a", "b", "c", "--", "d", "e", "f"], 2, 2);
+        for n in [0, 4] {
+            linch.items[n].separator = true
+        }
+        linch.wrap = true;
+        key(&mut linch, Command::Down);
+        assert_eq!(selected(&linch).as_deref(), Some("a"));
+        key(&mut linch, Command::Left);
+        assert_eq!(selected(&linch).as_deref(), Some("f"));
+        // forwards off the end lands past the leading divider
+        key(&mut linch, Command::Right);
+        assert_eq!(selected(&linch).as_deref(), Some("a"));
+        // Up onto the divider bounces back, which still counts as the top
+        key(&mut linch, Command::Up);
+        assert_eq!(selected(&linch).as_deref(), Some("f"));
+        key(&mut linch, Command::Down);
+        assert_eq!(selected(&linch).as_deref(), Some("a"));
+        // the top of a later column isn't the start of the list
+        key(&mut linch, Command::Right);
+        assert_eq!(selected(&linch).as_deref(), Some("c"));
+        key(&mut linch, Command::Up);
+        key(&mut linch, Command::Up);
+        assert_eq!(selected(&linch).as_deref(), Some("b"));
+        // without --wrap the ends hold
+        linch.wrap = false;
+        key(&mut linch, Command::Left);
+        key(&mut linch, Command::Up);
+        assert_eq!(selected(&linch).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn signal_reloads_theme() {
+        let mut linch = menu(&["a"], 4, 1);
+        linch.theme_base = theme::Theme {
+            foreground: Some(Color32::WHITE),
+            background: Some(Color32::BLACK),
+            accent: Some(Color32::BLUE),
+            opacity: Some(1.0),
+            scale: Some(1.0),
+            icon_theme: None,
+        };
+        linch.theme_fixed = vec!["opacity"];
+        theme::listen();
+        assert!(!theme::take_reload());
+        // SAFETY: raise has no preconditions, and the handler only sets the flag and writes the pipe
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0);
+        assert!(theme::take_reload());
+        assert!(!theme::take_reload(), "one signal is one reload");
+        // what update does with the flag, with the file's contents given directly
+        let ctx = Context::default();
+        linch.reload_theme(&ctx, theme::parse("accent #ff0000\nbackground #202020\nopacity 0.5\n"));
+        assert_eq!(
+            (linch.acc, linch.bg, linch.fg),
+            (Color32::RED, Color32::from_gray(32), Color32::WHITE)
+        );
+        // flags given on the command line still win
+        assert_eq!(linch.opacity, 1.0);
+        assert_eq!(ctx.style().visuals.window_fill, Color32::from_gray(32));
+    }
+} // }}}
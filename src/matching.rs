@@ -0,0 +1,51 @@
+// The search itself, kept apart from the window so other frontends filter the same way.
+
+use regex::Regex;
+
+/// The query as the search field compiles it, or None if it isn't a valid regex
+pub fn compile(input: &str, case_sensitive: bool) -> Option<Regex> {
+    if case_sensitive {
+        Regex::new(input).ok()
+    } else {
+        Regex::new(&(String::from("(?i)") + input)).ok()
+    }
+}
+
+/// The search test. Regex if compiled, else a literal prefix
+pub fn search_matches(input: &str, compiled: Option<&Regex>, t: &str, case_sensitive: bool) -> bool {
+    if let Some(re) = compiled {
+        re.is_match(t)
+    } else if case_sensitive {
+        t.starts_with(input)
+    } else {
+        let mut t = t.chars().flat_map(char::to_lowercase);
+        input.chars().flat_map(char::to_lowercase).all(|c| t.next() == Some(c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(input: &str, t: &str, case_sensitive: bool) -> bool {
+        search_matches(input, compile(input, case_sensitive).as_ref(), t, case_sensitive)
+    }
+
+    #[test]
+    fn regex_queries() {
+        assert!(matches("fire", "Firefox", false));
+        assert!(!matches("fire", "Firefox", true));
+        assert!(matches("fox$", "Firefox", false));
+        assert!(matches("^(gimp|krita)", "Krita", false));
+    }
+
+    #[test]
+    fn literal_fallback() {
+        // unbalanced, so matched as a plain prefix
+        assert!(compile("c++(", false).is_none());
+        assert!(matches("c++(", "C++(IDE)", false));
+        assert!(!matches("c++(", "C++(IDE)", true));
+        assert!(!matches("c++(", "an IDE for c++(", false));
+        assert!(matches("ÉD", "édit", false));
+    }
+}
@@ -0,0 +1,110 @@
+// The monitor's work area, so --width and --height percentages are known before the window opens.
+// Only X11 can be asked without a window. Wayland gives no work area to clients at all,
+// so there the window is resized to the monitor once open instead.
+
+use std::env;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+/// Size in points of the primary monitor less any panels, as winit will scale it
+pub fn work_area() -> Option<[f32; 2]> {
+    // winit only uses X11 on Wayland when told to, and XWayland's view would be scaled differently
+    if env::var_os("WAYLAND_DISPLAY").is_some() && env::var("WINIT_UNIX_BACKEND").as_deref() != Ok("x11") {
+        return None;
+    }
+    let (conn, screen) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots.get(screen)?;
+    let monitors = conn.randr_get_monitors(root.root, true).ok()?.reply().ok()?.monitors;
+    // eframe centers on winit's first monitor, which is the primary one if any
+    let (rect, mm) = match monitors.iter().find(|m| m.primary).or(monitors.first()) {
+        Some(m) => (
+            [m.x as i64, m.y as i64, m.width as i64, m.height as i64],
+            (m.width_in_millimeters, m.height_in_millimeters),
+        ),
+        None => (
+            [0, 0, root.width_in_pixels as i64, root.height_in_pixels as i64],
+            (root.width_in_millimeters as u32, root.height_in_millimeters as u32),
+        ),
+    };
+    // one rectangle over every monitor, less the panels and docks
+    let workarea = conn
+        .intern_atom(true, b"_NET_WORKAREA")
+        .ok()
+        .and_then(|c| c.reply().ok())
+        .filter(|a| a.atom != 0)
+        .and_then(|a| {
+            conn.get_property(false, root.root, a.atom, AtomEnum::CARDINAL, 0, 4)
+                .ok()
+        })
+        .and_then(|c| c.reply().ok())
+        .and_then(|r| Some(r.value32()?.map(i64::from).collect::<Vec<_>>()))
+        .and_then(|v| <[i64; 4]>::try_from(v).ok());
+    let [_, _, w, h] = workarea.and_then(|area| intersect(rect, area)).unwrap_or(rect);
+    let scale = scale_factor(&conn, (rect[2] as u32, rect[3] as u32), mm);
+    Some([w as f32 / scale, h as f32 / scale])
+}
+
+/// Overlap of two `[x, y, width, height]` rectangles
+fn intersect(a: [i64; 4], b: [i64; 4]) -> Option<[i64; 4]> {
+    let (x, y) = (a[0].max(b[0]), a[1].max(b[1]));
+    let (right, bottom) = ((a[0] + a[2]).min(b[0] + b[2]), (a[1] + a[3]).min(b[1] + b[3]));
+    (right > x && bottom > y).then_some([x, y, right - x, bottom - y])
+}
+
+/// winit's X11 scale factor: WINIT_X11_SCALE_FACTOR, else Xft.dpi, else guessed from the monitor's size.
+/// Settings daemons put Xft.dpi in the resources as well as XSETTINGS, so only the former is read
+fn scale_factor(conn: &impl Connection, px: (u32, u32), mm: (u32, u32)) -> f32 {
+    let forced = env::var("WINIT_X11_SCALE_FACTOR").ok();
+    if let Some(scale) = forced.as_deref().and_then(|s| s.parse::<f32>().ok()) {
+        if scale.is_finite() && scale > 0.0 {
+            return scale;
+        }
+    }
+    if forced.as_deref() != Some("randr") {
+        let dpi = x11rb::resource_manager::new_from_default(conn)
+            .ok()
+            .and_then(|db| db.get_value::<f32>("Xft.dpi", "").ok().flatten());
+        if let Some(dpi) = dpi.filter(|d| *d > 0.0) {
+            return dpi / 96.0;
+        }
+    }
+    randr_scale(px, mm)
+}
+
+/// Pixels per millimeter in twelfths of 96 DPI, as winit does
+fn randr_scale((w, h): (u32, u32), (w_mm, h_mm): (u32, u32)) -> f32 {
+    if w_mm == 0 || h_mm == 0 {
+        return 1.0;
+    }
+    let ppmm = ((w as f64 * h as f64) / (w_mm as f64 * h_mm as f64)).sqrt();
+    let scale = ((ppmm * (12.0 * 25.4 / 96.0)).round() / 12.0).max(1.0);
+    if scale <= 20.0 {
+        scale as f32
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn work_area_overlap() {
+        // a top panel over both of two side by side monitors
+        let panel = [0, 30, 3840, 1050];
+        assert_eq!(intersect([0, 0, 1920, 1080], panel), Some([0, 30, 1920, 1050]));
+        assert_eq!(intersect([1920, 0, 1920, 1080], panel), Some([1920, 30, 1920, 1050]));
+        assert_eq!(intersect([0, 0, 1920, 1080], [1920, 0, 1920, 1080]), None);
+    }
+
+    #[test]
+    fn randr_scales() {
+        // 24" 1080p never goes below 1, 27" 4k is in twelfths
+        assert_eq!(randr_scale((1920, 1080), (531, 299)), 1.0);
+        assert!((randr_scale((3840, 2160), (597, 336)) - 20.0 / 12.0).abs() < 1e-6);
+        assert_eq!(randr_scale((1920, 1080), (0, 0)), 1.0);
+    }
+}
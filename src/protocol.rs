@@ -0,0 +1,162 @@
+// Every JSON object linch reads or writes is defined here so the
+// protocol only changes when this file does.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Bump whenever a JSON surface changes incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Adds the top level `linch_protocol` field to any object
+#[derive(Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub linch_protocol: u32,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+/// Serialize `body` as a single line object tagged with the protocol version
+pub fn to_json<T: Serialize>(body: T) -> String {
+    serde_json::to_string(&Envelope {
+        linch_protocol: PROTOCOL_VERSION,
+        body,
+    })
+    .expect("Protocol types must always serialize")
+}
+
+/// Parse an object written against this protocol version.
+/// Missing required fields are an error while unknown extra fields are ignored,
+/// so optional additions don't break older readers. With `strict` they're an error too,
+/// catching misspelled keys
+pub fn from_json<T: Serialize + DeserializeOwned>(data: &str, strict: bool) -> Result<T, String> {
+    let invalid = |e: serde_json::Error| format!("Invalid linch protocol JSON: {}", e);
+    let value: serde_json::Value = serde_json::from_str(data).map_err(invalid)?;
+    let envelope: Envelope<T> = serde_json::from_value(value.clone()).map_err(invalid)?;
+    if envelope.linch_protocol != PROTOCOL_VERSION {
+        return Err(format!(
+            "Unsupported linch_protocol {}, expected {}",
+            envelope.linch_protocol, PROTOCOL_VERSION
+        ));
+    }
+    if strict {
+        // deny_unknown_fields doesn't work through flatten, so any key a round trip drops is unknown
+        let known = serde_json::to_value(&envelope).expect("Protocol types must always serialize");
+        let unknown = value
+            .as_object()
+            .and_then(|given| given.keys().find(|k| known.get(k.as_str()).is_none()));
+        if let Some(key) = unknown {
+            return Err(format!("Unknown field \"{}\" in strict mode", key));
+        }
+    }
+    Ok(envelope.body)
+}
+
+/// One timing from `linch bench`
+#[derive(Serialize, Deserialize)]
+pub struct BenchResult {
+    pub name: String,
+    /// Items in the workload
+    pub size: usize,
+    /// Best of the runs
+    pub micros: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+/// One line of `linch dmenu --json` input
+#[derive(Serialize, Deserialize)]
+pub struct DmenuEntry {
+    pub name: String,
+    /// Icon name or path, shown with --icons
+    pub icon: Option<String>,
+    /// Shown with --detail-width
+    pub detail: Option<String>,
+}
+
+/// One line of `--list --json`
+#[derive(Serialize, Deserialize)]
+pub struct ListedItem {
+    pub name: String,
+    /// Desktop entry or binary path
+    pub file: Option<String>,
+    pub exec: Option<String>,
+    pub icon: Option<String>,
+    pub no_display: bool,
+}
+
+/// `--version-json`, for bug reports
+#[derive(Serialize, Deserialize)]
+pub struct VersionReport {
+    pub version: String,
+    /// Cargo features compiled in
+    pub features: Vec<String>,
+    pub renderer: String,
+    /// x11 or wayland
+    pub session_type: Option<String>,
+    /// XDG_CURRENT_DESKTOP
+    pub desktop: Option<String>,
+    /// Scale variables that were set, as VAR=value
+    pub scale_sources: Vec<String>,
+    /// Resulting scale before --scale
+    pub scale: f32,
+    /// From the GTK settings
+    pub icon_theme: Option<String>,
+    pub data_dirs: Vec<String>,
+    pub config_dir: Option<String>,
+    pub state_dir: Option<String>,
+    pub cache_dir: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Written, read back, and written again unchanged
+    fn round_trip<T: Serialize + DeserializeOwned>(body: T) {
+        let json = to_json(body);
+        let read: T = from_json(&json, true).unwrap();
+        assert_eq!(to_json(read), json);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(DmenuEntry {
+            name: String::from("Firefox"),
+            icon: Some(String::from("firefox")),
+            detail: None,
+        });
+        round_trip(ListedItem {
+            name: String::from("Vim"),
+            file: Some(String::from("/usr/share/applications/vim.desktop")),
+            exec: Some(String::from("vim %F")),
+            icon: None,
+            no_display: false,
+        });
+        round_trip(BenchReport {
+            results: vec![BenchResult {
+                name: String::from("sort"),
+                size: 100,
+                micros: 42,
+            }],
+        });
+    }
+
+    #[test]
+    fn versions_and_fields() {
+        let entry = |json: &str, strict| from_json::<DmenuEntry>(json, strict).map(|e| e.name);
+        assert_eq!(entry(r#"{"linch_protocol":1,"name":"a"}"#, true).as_deref(), Ok("a"));
+        assert!(entry(r#"{"linch_protocol":2,"name":"a"}"#, false).is_err());
+        assert!(entry(r#"{"name":"a"}"#, false).is_err());
+        assert!(entry(r#"{"linch_protocol":1,"icon":"a"}"#, false).is_err());
+        // unknown fields only matter when strict
+        let extra = r#"{"linch_protocol":1,"name":"a","iconn":"b"}"#;
+        assert_eq!(entry(extra, false).as_deref(), Ok("a"));
+        assert_eq!(
+            entry(extra, true),
+            Err(String::from("Unknown field \"iconn\" in strict mode"))
+        );
+    }
+}
@@ -0,0 +1,418 @@
+// Turning icon files into textures, and keeping the results between launches.
+// Finding the files is up to the lookup in linch::icons.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::read_to_string;
+use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use eframe::egui::{Color32, ColorImage};
+use resvg::{tiny_skia, usvg};
+
+/// A plain application window, for when no theme has the fallback icon
+const FALLBACK_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="48" height="48" viewBox="0 0 48 48">
+<rect x="4" y="6" width="40" height="36" rx="4" fill="#9a9a9a"/>
+<rect x="8" y="16" width="32" height="22" rx="1.5" fill="#e6e6e6"/>
+<circle cx="10" cy="11" r="2" fill="#e6e6e6"/>
+<circle cx="16" cy="11" r="2" fill="#e6e6e6"/>
+<path d="M13 22l6 5-6 5" fill="none" stroke="#6a6a6a" stroke-width="2.5" stroke-linecap="round" stroke-linejoin="round"/>
+<path d="M22 33h10" stroke="#6a6a6a" stroke-width="2.5" stroke-linecap="round"/>
+</svg>
+"##;
+
+/// The built in fallback icon, written to the cache dir so it can be drawn like any other file
+pub fn fallback_file() -> Option<PathBuf> {
+    let file = crate::storage::cache_file("fallback.svg")?;
+    if read_to_string(&file).is_ok_and(|s| s == FALLBACK_SVG) {
+        return Some(file);
+    }
+    let result = file
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&file, FALLBACK_SVG));
+    match result {
+        Ok(()) => Some(file),
+        Err(e) => {
+            eprintln!("Could not write fallback icon {}\n{}", file.display(), e);
+            None
+        }
+    }
+}
+
+/// Rasterize an SVG to fit `w`x`h` or decode any other image, shrinking it to fit if larger
+pub fn render(path: &Path, w: u32, h: u32) -> Result<ColorImage, String> {
+    if path.extension() == Some(OsStr::new("svg")) {
+        let data = std::fs::read(path).map_err(|e| e.to_string())?;
+        let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|e| e.to_string())?;
+        let scale = (w as f32 / tree.size().width()).min(h as f32 / tree.size().height());
+        let mut pixbuf = tiny_skia::Pixmap::new(w, h).ok_or("Icon size is zero")?;
+        resvg::render(
+            &tree,
+            tiny_skia::Transform::from_scale(scale, scale),
+            &mut pixbuf.as_mut(),
+        );
+        Ok(ColorImage::from_rgba_unmultiplied(
+            [pixbuf.width() as usize, pixbuf.height() as usize],
+            &pixbuf.take(),
+        ))
+    } else if path.extension() == Some(OsStr::new("xpm")) {
+        decode_xpm(&read_to_string(path).map_err(|e| e.to_string())?)
+    } else {
+        // sniffed as well, since ICO and GIF icons are often named after something else.
+        // ICO takes its largest entry and animations their first frame.
+        // Some decoders panic on malformed files, which would otherwise take down every icon
+        let mut image = std::panic::catch_unwind(|| {
+            image::io::Reader::open(path)
+                .and_then(|r| r.with_guessed_format())
+                .map_err(|e| e.to_string())?
+                .decode()
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|_| String::from("Decoder panicked"))??;
+        // filtered here as the GPU would only sample a few of the source pixels.
+        // Averaging is several times faster than Lanczos for the 512px and up icons some apps ship alone
+        if image.width() >= w * 2 || image.height() >= h * 2 {
+            image = image.thumbnail(w, h)
+        } else if image.width() > w || image.height() > h {
+            image = image.resize(w, h, image::imageops::FilterType::Lanczos3)
+        }
+        Ok(ColorImage::from_rgba_unmultiplied(
+            [image.width() as usize, image.height() as usize],
+            &image.into_rgba8(),
+        ))
+    }
+}
+
+/// XPM images, which are C source with one string per header, color, and pixel row.
+/// Only the color context is read
+fn decode_xpm(data: &str) -> Result<ColorImage, String> {
+    // {{{
+    // the text between every other quote, skipping the C around it
+    let mut strings = data.split('"').skip(1).step_by(2);
+    let header: Vec<usize> = strings
+        .next()
+        .ok_or("XPM has no header")?
+        .split_whitespace()
+        .take(4)
+        .map(|n| n.parse().map_err(|_| "XPM header is not numeric"))
+        .collect::<Result<_, _>>()?;
+    let [w, h, ncolors, cpp] = header[..] else {
+        return Err(String::from("XPM header is incomplete"));
+    };
+    let mut colors = HashMap::new();
+    for line in strings.by_ref().take(ncolors) {
+        let (key, spec) = line.split_at_checked(cpp).ok_or("XPM color is too short")?;
+        let tokens: Vec<&str> = spec.split_whitespace().collect();
+        // pairs of context and color, where the color may be several words
+        let color = tokens
+            .iter()
+            .position(|t| *t == "c")
+            .map(|n| {
+                tokens[n + 1..]
+                    .iter()
+                    .take_while(|t| !["m", "g", "g4", "s"].contains(t))
+            })
+            .map(|words| words.copied().collect::<Vec<&str>>().join(" "))
+            .or_else(|| tokens.last().map(|t| t.to_string()))
+            .unwrap_or_default();
+        colors.insert(key.to_string(), xpm_color(&color)?);
+    }
+    // the header alone could otherwise ask for any allocation
+    let area = w.checked_mul(h).filter(|a| *a <= RASTER_MAX_SIDE * RASTER_MAX_SIDE);
+    let mut pixels = Vec::with_capacity(area.ok_or("XPM is too large")?);
+    for row in strings.take(h) {
+        let mut row = row.chars();
+        for _ in 0..w {
+            let key: String = row.by_ref().take(cpp).collect();
+            pixels.push(*colors.get(&key).ok_or("XPM pixel has no color")?);
+        }
+    }
+    if pixels.len() != w * h {
+        return Err(String::from("XPM is missing rows"));
+    }
+    Ok(ColorImage { size: [w, h], pixels })
+} // }}}
+
+/// `#rgb` style hex with 1 to 4 digits per channel, `None`, or an X11 color name
+fn xpm_color(color: &str) -> Result<Color32, String> {
+    if let Some(hex) = color.strip_prefix('#') {
+        let len = hex.len() / 3;
+        if !(1..=4).contains(&len) || hex.len() % 3 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("Invalid XPM color {}", color));
+        }
+        // of which the top byte is kept
+        let channel = |n: usize| {
+            let value = u32::from_str_radix(&hex[n * len..(n + 1) * len], 16).unwrap_or(0);
+            (value * 255 / ((1 << (4 * len as u32)) - 1)) as u8
+        };
+        return Ok(Color32::from_rgb(channel(0), channel(1), channel(2)));
+    }
+    let name = color.to_lowercase().replace(' ', "");
+    if name == "none" {
+        return Ok(Color32::TRANSPARENT);
+    }
+    // rounded like the server's own table
+    let percent = name.strip_prefix("gray").or_else(|| name.strip_prefix("grey"));
+    if let Some(level) = percent.and_then(|p| p.parse::<u32>().ok()).filter(|p| *p <= 100) {
+        return Ok(Color32::from_gray(((level * 255 + 49) / 100) as u8));
+    }
+    x11_colors()
+        .get(&name)
+        .copied()
+        .ok_or_else(|| format!("Unknown XPM color {}", color))
+}
+
+/// The X server's color names, or the basic ones if its rgb.txt isn't installed.
+/// Keys are lowercase without spaces, as the names are matched
+fn x11_colors() -> &'static HashMap<String, Color32> {
+    static COLORS: std::sync::OnceLock<HashMap<String, Color32>> = std::sync::OnceLock::new();
+    COLORS.get_or_init(|| {
+        let mut colors: HashMap<String, Color32> = [
+            ("black", [0, 0, 0]),
+            ("white", [255, 255, 255]),
+            ("gray", [190, 190, 190]),
+            ("grey", [190, 190, 190]),
+            ("red", [255, 0, 0]),
+            ("green", [0, 255, 0]),
+            ("blue", [0, 0, 255]),
+            ("yellow", [255, 255, 0]),
+            ("cyan", [0, 255, 255]),
+            ("magenta", [255, 0, 255]),
+            ("orange", [255, 165, 0]),
+            ("purple", [160, 32, 240]),
+            ("brown", [165, 42, 42]),
+            ("navy", [0, 0, 128]),
+            ("maroon", [176, 48, 96]),
+        ]
+        .into_iter()
+        .map(|(name, [r, g, b])| (name.to_string(), Color32::from_rgb(r, g, b)))
+        .collect();
+        let table = ["/usr/share/X11/rgb.txt", "/etc/X11/rgb.txt"]
+            .into_iter()
+            .find_map(|f| read_to_string(f).ok())
+            .unwrap_or_default();
+        // `255 250 250\t\tsnow` lines, with ! comments
+        for line in table.lines().filter(|l| !l.starts_with('!')) {
+            let mut words = line.split_whitespace();
+            let rgb: Vec<u8> = words.by_ref().take(3).filter_map(|n| n.parse().ok()).collect();
+            let name: String = words.collect::<String>().to_lowercase();
+            if let [r, g, b] = rgb[..] {
+                if !name.is_empty() {
+                    colors.insert(name, Color32::from_rgb(r, g, b));
+                }
+            }
+        }
+        colors
+    })
+}
+
+// ### Raster Cache {{{
+
+/// Bump whenever the layout below changes so old caches are ignored
+const RASTER_MAGIC: &[u8] = b"linch-icons-1\n";
+
+/// Larger than any icon is drawn, so bigger sizes only come from a damaged file
+const RASTER_MAX_SIDE: usize = 1 << 14;
+
+/// Source mtime as seconds and nanoseconds
+fn mtime(path: &Path) -> Option<(i64, i64)> {
+    path.metadata().ok().map(|m| (m.mtime(), m.mtime_nsec()))
+}
+
+/// Rendered icons kept between launches, each dropped once its source file changes.
+/// The layout is the magic line followed by repeated
+/// `key, path, mtime, mtime_nsec, width, height, premultiplied RGBA` records,
+/// strings being u32 length prefixed and all numbers little endian.
+/// Sources that failed to decode are stored as 0x0 tombstones
+#[derive(Default)]
+pub struct RasterCache(HashMap<String, RasterEntry>);
+
+/// Source, its mtime when rendered, and the image unless it failed to decode
+type RasterEntry = (PathBuf, (i64, i64), Option<ColorImage>);
+
+impl RasterCache {
+    /// Empty if the file is missing, foreign, or truncated
+    pub fn load(file: &Path) -> Self {
+        let mut result = HashMap::new();
+        let Ok(data) = std::fs::read(file) else {
+            return Self::default();
+        };
+        let Some(mut data) = data.strip_prefix(RASTER_MAGIC) else {
+            return Self::default();
+        };
+        let mut take = |n: usize| -> Option<&[u8]> {
+            let (head, tail) = data.split_at_checked(n)?;
+            data = tail;
+            Some(head)
+        };
+        // bails on the first short read, keeping whatever was complete
+        while let Some(len) = take(4) {
+            let Some(entry) = (|| {
+                let key = String::from_utf8(take(u32::from_le_bytes(len.try_into().ok()?) as usize)?.to_vec()).ok()?;
+                let path_len = u32::from_le_bytes(take(4)?.try_into().ok()?) as usize;
+                let path = PathBuf::from(OsString::from_vec(take(path_len)?.to_vec()));
+                let secs = i64::from_le_bytes(take(8)?.try_into().ok()?);
+                let nsecs = i64::from_le_bytes(take(8)?.try_into().ok()?);
+                let w = u32::from_le_bytes(take(4)?.try_into().ok()?) as usize;
+                let h = u32::from_le_bytes(take(4)?.try_into().ok()?) as usize;
+                if w > RASTER_MAX_SIDE || h > RASTER_MAX_SIDE {
+                    return None;
+                }
+                let pixels = take(w.checked_mul(h)?.checked_mul(4)?)?
+                    .chunks_exact(4)
+                    .map(|p| Color32::from_rgba_premultiplied(p[0], p[1], p[2], p[3]))
+                    .collect();
+                let image = (w * h > 0).then_some(ColorImage { size: [w, h], pixels });
+                Some((key, (path, (secs, nsecs), image)))
+            })() else {
+                break;
+            };
+            result.insert(entry.0, entry.1);
+        }
+        Self(result)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Source path and image for `key` if the source is unchanged since rendering.
+    /// The image is None if the source failed to decode
+    pub fn get(&self, key: &str) -> Option<(PathBuf, Option<ColorImage>)> {
+        self.0
+            .get(key)
+            .filter(|(path, time, _)| mtime(path) == Some(*time))
+            .map(|(path, _, image)| (path.clone(), image.clone()))
+    }
+
+    /// Replace the file with exactly `entries`, so icons no longer shown fall out.
+    /// Written beside the target then renamed over it
+    pub fn save<'a>(
+        file: &Path,
+        entries: impl Iterator<Item = (String, &'a Path, Option<&'a ColorImage>)>,
+    ) -> std::io::Result<()> {
+        let mut data = RASTER_MAGIC.to_vec();
+        for (key, path, image) in entries {
+            let Some((secs, nsecs)) = mtime(path) else {
+                continue;
+            };
+            let path = path.as_os_str().as_bytes();
+            data.extend((key.len() as u32).to_le_bytes());
+            data.extend(key.as_bytes());
+            data.extend((path.len() as u32).to_le_bytes());
+            data.extend(path);
+            data.extend(secs.to_le_bytes());
+            data.extend(nsecs.to_le_bytes());
+            let size = image.map_or([0, 0], |i| i.size);
+            data.extend((size[0] as u32).to_le_bytes());
+            data.extend((size[1] as u32).to_le_bytes());
+            data.extend(image.iter().flat_map(|i| i.pixels.iter().flat_map(|p| p.to_array())));
+        }
+        let mut temp = file.as_os_str().to_os_string();
+        temp.push(".tmp");
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?
+        }
+        crate::storage::create_private(Path::new(&temp))?.write_all(&data)?;
+        std::fs::rename(&temp, file)
+    }
+}
+
+// ### Raster Cache }}}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xpm() {
+        let xpm = r##"static char * x[] = {"2 1 2 1", ". c #f00", "# c None", ".#"};"##;
+        let image = decode_xpm(xpm).unwrap();
+        assert_eq!(image.pixels, [Color32::RED, Color32::TRANSPARENT]);
+        assert!(decode_xpm(r#""4000000000 4000000000 1 1", ". c red""#).is_err());
+        assert!(decode_xpm(r#""1 1 1 1", ". c nosuchcolor", ".""#).is_err());
+    }
+
+    #[test]
+    fn xpm_colors() {
+        assert_eq!(xpm_color("#f00"), Ok(Color32::RED));
+        assert_eq!(xpm_color("#00ff00"), Ok(Color32::GREEN));
+        assert_eq!(xpm_color("#00000000ffff"), Ok(Color32::BLUE));
+        assert_eq!(xpm_color("gray50"), Ok(Color32::from_gray(127)));
+        assert_eq!(xpm_color("Orange"), Ok(Color32::from_rgb(255, 165, 0)));
+        assert_eq!(xpm_color("NONE"), Ok(Color32::TRANSPARENT));
+        assert!(xpm_color("#ff").is_err());
+        assert!(xpm_color("#ggg").is_err());
+        assert!(xpm_color("chartreuse-ish").is_err());
+    }
+
+    #[test]
+    fn damaged_cache_is_ignored() {
+        let file = std::env::temp_dir().join(format!("linch-raster-test-{}", std::process::id()));
+        let image = ColorImage::new([2, 2], Color32::RED);
+        RasterCache::save(
+            &file,
+            std::iter::once((String::from("icon"), Path::new("/"), Some(&image))),
+        )
+        .unwrap();
+        assert_eq!(RasterCache::load(&file).len(), 1);
+
+        // a size whose byte count overflows, then one that's merely absurd
+        let mut data = std::fs::read(&file).unwrap();
+        let size = data.len() - 2 * 2 * 4 - 8;
+        for side in [u32::MAX, 1 << 20] {
+            data[size..size + 4].copy_from_slice(&side.to_le_bytes());
+            data[size + 4..size + 8].copy_from_slice(&side.to_le_bytes());
+            std::fs::write(&file, &data).unwrap();
+            assert_eq!(RasterCache::load(&file).len(), 0);
+        }
+
+        // truncated mid record
+        std::fs::write(&file, &data[..data.len() / 2]).unwrap();
+        assert_eq!(RasterCache::load(&file).len(), 0);
+        std::fs::remove_file(&file).ok();
+    }
+
+    #[test]
+    fn undecodable_icons_are_tombstoned() {
+        let dir = std::env::temp_dir().join(format!("linch-raster-bad-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (png, svg) = (dir.join("bad.png"), dir.join("bad.svg"));
+        image::RgbaImage::new(16, 16).save(&png).unwrap();
+        assert!(render(&png, 8, 8).is_ok());
+        let data = std::fs::read(&png).unwrap();
+        std::fs::write(&png, &data[..data.len() / 2]).unwrap();
+        std::fs::write(&svg, "<svg xmlns=\"http://www.w3.org/2000/svg\"><rect").unwrap();
+        assert!(render(&png, 8, 8).is_err());
+        assert!(render(&svg, 8, 8).is_err());
+
+        // kept as 0x0 so the next launch doesn't decode them again
+        let file = dir.join("icons.bin");
+        RasterCache::save(
+            &file,
+            [
+                (String::from("png"), png.as_path(), None),
+                (String::from("svg"), svg.as_path(), None),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        let cache = RasterCache::load(&file);
+        assert_eq!(cache.get("png"), Some((png.clone(), None)));
+        assert_eq!(cache.get("svg"), Some((svg.clone(), None)));
+
+        // until the source changes
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&svg)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(cache.get("svg"), None);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,172 @@
+// Where linch keeps its files. Launch counts and history can't be rebuilt so they
+// live under XDG_STATE_HOME, while the cache dir only holds what can be regenerated.
+
+use std::env;
+use std::fs::{copy, create_dir_all, read, remove_file, set_permissions, File, Permissions};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Set by --cache-dir or the `cache_dir` config file
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// $XDG_STATE_HOME/linch or ~/.local/state/linch
+pub fn state_dir() -> Option<PathBuf> {
+    if let Ok(xdg_state) = env::var("XDG_STATE_HOME") {
+        Some(PathBuf::from(xdg_state + "/linch"))
+    } else {
+        env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home + "/.local/state/linch"))
+    }
+}
+
+/// Use `dir` instead of the XDG cache dir for the rest of the run
+pub fn set_cache_dir(dir: PathBuf) {
+    CACHE_DIR.set(dir).ok();
+}
+
+/// The --cache-dir override or the XDG one
+pub fn cache_dir() -> Option<PathBuf> {
+    CACHE_DIR.get().cloned().or_else(xdg_cache_dir)
+}
+
+fn cache_override() -> Option<&'static Path> {
+    CACHE_DIR.get().map(PathBuf::as_path)
+}
+
+/// $XDG_CACHE_HOME or ~/.cache. Shared with other programs, so files are prefixed `linch_`
+fn xdg_cache_dir() -> Option<PathBuf> {
+    if let Ok(xdg_cache) = env::var("XDG_CACHE_HOME") {
+        Some(PathBuf::from(xdg_cache))
+    } else {
+        env::var("HOME").ok().map(|home| PathBuf::from(home + "/.cache"))
+    }
+}
+
+/// Launch counts for the cache `name`, kept with everything else when the cache dir is overridden.
+/// None if neither XDG_STATE_HOME nor HOME is set
+pub fn counts_file(name: &str) -> Option<PathBuf> {
+    counts_file_in(cache_override(), name)
+}
+
+/// `counts_file` with `dir` as the cache dir override
+fn counts_file_in(dir: Option<&Path>, name: &str) -> Option<PathBuf> {
+    assert!(!name.is_empty());
+    match dir {
+        Some(dir) => Some(dir.join(String::from("linch_cache_") + name)),
+        None => Some(state_dir()?.join(String::from("cache_") + name)),
+    }
+}
+
+/// Regenerable data such as rendered icons.
+/// None if there's no override and neither XDG_CACHE_HOME nor HOME is set
+pub fn cache_file(name: &str) -> Option<PathBuf> {
+    cache_file_in(cache_override(), name)
+}
+
+/// `cache_file` with `dir` as the cache dir override
+fn cache_file_in(dir: Option<&Path>, name: &str) -> Option<PathBuf> {
+    let dir = dir.map(Path::to_path_buf).or_else(xdg_cache_dir)?;
+    Some(dir.join(String::from("linch_") + name))
+}
+
+/// False with a warning if the file exists but belongs to another user
+pub fn usable(file: &Path) -> bool {
+    match file.metadata() {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        Ok(meta) if meta.uid() != unsafe { libc::geteuid() } => {
+            eprintln!("{} is owned by another user. Ignoring it", file.display());
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Truncate or create `file` readable only by the user. The mode is set again
+/// as creating only applies it to new files, and an old one may be left from a failed write
+pub fn create_private(file: &Path) -> std::io::Result<File> {
+    let f = File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(file)?;
+    f.set_permissions(Permissions::from_mode(0o600))?;
+    Ok(f)
+}
+
+/// Move counts from where older versions kept them in the cache dir.
+/// The old file is only removed once the copy reads back identical
+pub fn migrate_counts(name: &str) {
+    migrate_counts_in(cache_override(), name)
+}
+
+/// `migrate_counts` with `dir` as the cache dir override
+fn migrate_counts_in(dir: Option<&Path>, name: &str) {
+    // older versions never had --cache-dir, and an overridden dir shouldn't take the usual counts
+    if dir.is_some() {
+        return;
+    }
+    let (Some(new), Some(old)) = (
+        counts_file_in(dir, name),
+        xdg_cache_dir().map(|d| d.join(String::from("linch_") + name)),
+    ) else {
+        return;
+    };
+    if new.exists() || !old.is_file() || !usable(&old) {
+        return;
+    }
+    let result = new
+        .parent()
+        .map_or(Ok(()), create_dir_all)
+        .and_then(|_| copy(&old, &new))
+        // copying keeps the old file's mode, which predates caches being private
+        .and_then(|_| set_permissions(&new, Permissions::from_mode(0o600)))
+        // undated lines are aged by the file's mtime, which a copy would reset
+        .and_then(|_| {
+            File::options()
+                .write(true)
+                .open(&new)?
+                .set_modified(old.metadata()?.modified()?)
+        })
+        .and_then(|_| {
+            if read(&old)? == read(&new)? {
+                remove_file(&old)
+            } else {
+                remove_file(&new)?;
+                Err(std::io::Error::other("copy differs from the original"))
+            }
+        });
+    if let Err(e) = result {
+        eprintln!("Could not move cache {} to {}\n{}", old.display(), new.display(), e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_files() {
+        let file = env::temp_dir().join(format!("linch-private-{}", std::process::id()));
+        std::fs::write(&file, "old").unwrap();
+        set_permissions(&file, Permissions::from_mode(0o644)).unwrap();
+        create_private(&file).unwrap();
+        assert_eq!(file.metadata().unwrap().mode() & 0o777, 0o600);
+        assert_eq!(read(&file).unwrap(), b"");
+        remove_file(file).ok();
+    }
+
+    #[test]
+    fn override_holds_counts() {
+        let dir = env::temp_dir().join(format!("linch-storage-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let over = Some(dir.as_path());
+        assert_eq!(counts_file_in(over, "app"), Some(dir.join("linch_cache_app")));
+        assert_eq!(cache_file_in(over, "icons.bin"), Some(dir.join("linch_icons.bin")));
+        // nothing is moved into an overridden dir
+        migrate_counts_in(over, "app");
+        assert!(!dir.exists());
+    }
+}
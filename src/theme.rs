@@ -0,0 +1,144 @@
+// The `theme` file in the config dir sets the look without flags,
+// and is read again on SIGUSR1 so an open window can be restyled while tuning it.
+// The icon theme is only read on startup as every icon would need looking up again.
+
+use std::fs::{read_to_string, File};
+use std::io::{ErrorKind, Read};
+use std::os::fd::FromRawFd;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use eframe::egui::{Color32, Context};
+
+use crate::{config_dir, parse_color};
+
+/// Option names a theme may set. Any of them given as a flag always wins
+pub const KEYS: [&str; 6] = ["foreground", "background", "accent", "opacity", "scale", "icon_theme"];
+
+/// One line per option as its long flag name and value, such as `accent oklch 70% 60% 95`
+#[derive(Default)]
+pub struct Theme {
+    pub foreground: Option<Color32>,
+    pub background: Option<Color32>,
+    pub accent: Option<Color32>,
+    pub opacity: Option<f32>,
+    pub scale: Option<f32>,
+    pub icon_theme: Option<String>,
+}
+
+impl Theme {
+    /// Fill in unset options from `base`, which also wins for the `fixed` ones
+    pub fn or(self, base: &Theme, fixed: &[&str]) -> Theme {
+        fn pick<T>(key: &str, fixed: &[&str], ours: Option<T>, base: Option<T>) -> Option<T> {
+            if fixed.contains(&key) {
+                base
+            } else {
+                ours.or(base)
+            }
+        }
+        Theme {
+            foreground: pick("foreground", fixed, self.foreground, base.foreground),
+            background: pick("background", fixed, self.background, base.background),
+            accent: pick("accent", fixed, self.accent, base.accent),
+            opacity: pick("opacity", fixed, self.opacity, base.opacity),
+            scale: pick("scale", fixed, self.scale, base.scale),
+            icon_theme: pick("icon_theme", fixed, self.icon_theme, base.icon_theme.clone()),
+        }
+    }
+}
+
+pub fn parse(data: &str) -> Theme {
+    // {{{
+    let mut theme = Theme::default();
+    for line in data.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let value = value.trim();
+        let number = || value.parse::<f32>().map_err(|e| e.to_string());
+        let result = match key {
+            "foreground" => parse_color(value).map(|c| theme.foreground = Some(c)),
+            "background" => parse_color(value).map(|c| theme.background = Some(c)),
+            "accent" => parse_color(value).map(|c| theme.accent = Some(c)),
+            "opacity" => number().map(|n| theme.opacity = Some(n)),
+            "scale" => number().map(|n| theme.scale = Some(n)),
+            "icon-theme" => {
+                theme.icon_theme = Some(value.to_string());
+                Ok(())
+            }
+            _ => Err(String::from("Unknown option")),
+        };
+        if let Err(e) = result {
+            eprintln!("Could not parse theme line \"{}\"\n{}", line, e)
+        }
+    }
+    theme
+} // }}}
+
+pub fn get() -> Theme {
+    config_dir()
+        .and_then(|mut dir| {
+            dir.push("theme");
+            read_to_string(dir).ok()
+        })
+        .map(|data| parse(&data))
+        .unwrap_or_default()
+}
+
+static RELOAD: AtomicBool = AtomicBool::new(false);
+/// Write end of the pipe waking the watcher thread
+static WAKE: AtomicI32 = AtomicI32::new(-1);
+/// Read end, until `watch` takes it
+static WAKE_READ: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    // only async-signal-safe calls in here
+    RELOAD.store(true, Ordering::SeqCst);
+    let fd = WAKE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        // SAFETY: write is async-signal-safe and the buffer outlives the call
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// Catch SIGUSR1 from now on, as it would otherwise end the process.
+/// Called first thing so a signal during startup is only a reload waiting for the window
+pub fn listen() {
+    let mut fds = [0; 2];
+    // SAFETY: fds has room for both ends
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        eprintln!("Could not create theme reload pipe");
+        return;
+    }
+    WAKE_READ.store(fds[0], Ordering::SeqCst);
+    WAKE.store(fds[1], Ordering::SeqCst);
+    // SAFETY: the handler only touches atomics and write
+    unsafe { libc::signal(libc::SIGUSR1, on_signal as *const () as libc::sighandler_t) };
+}
+
+/// Repaint `ctx` whenever SIGUSR1 arrives so `update` can pick up `take_reload`
+pub fn watch(ctx: Context) {
+    let fd = WAKE_READ.swap(-1, Ordering::SeqCst);
+    if fd < 0 {
+        return;
+    }
+    // SAFETY: the read end was only ever stored for this, and swapping it out makes it owned here alone
+    let mut wake = unsafe { File::from_raw_fd(fd) };
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 16];
+        loop {
+            match wake.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => ctx.request_repaint(),
+                // the byte is still coming if the signal landed on this thread
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+/// True once per received SIGUSR1
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
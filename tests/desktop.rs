@@ -0,0 +1,132 @@
+// Real world shaped entries under tests/fixtures, read the way discovery reads them
+#![cfg(feature = "discovery")]
+
+use std::path::{Path, PathBuf};
+
+use linch::desktop::{parse_entry, MAIN_GROUP};
+use linch::discovery::{applications_in, EntryError, Item};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/applications")
+        .join(name)
+}
+
+fn item(name: &str) -> Item {
+    match Item::from_desktop(fixture(name)) {
+        Ok(item) => item,
+        Err(e) => panic!("{}: {}", name, e),
+    }
+}
+
+#[test]
+fn typical_entry() {
+    let firefox = item("firefox.desktop");
+    assert_eq!(&*firefox.name, "Firefox");
+    assert_eq!(firefox.exec.as_deref(), Some("firefox %u"));
+    assert_eq!(firefox.icon.as_deref(), Some("firefox"));
+    assert_eq!(firefox.comment.as_deref(), Some("Browse the Web"));
+    assert_eq!(firefox.keywords, ["Internet", "WWW", "Browser", "Web"]);
+    assert_eq!(firefox.categories, ["Network", "WebBrowser"]);
+    assert_eq!(firefox.mime_types.len(), 3);
+    assert!(!firefox.terminal && !firefox.no_display);
+    let actions: Vec<_> = firefox
+        .actions
+        .iter()
+        .map(|a| (&*a.name, a.exec.as_deref(), a.icon.as_deref()))
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            ("New Window", Some("firefox --new-window %u"), Some("firefox")),
+            (
+                "New Private Window",
+                Some("firefox --private-window %u"),
+                Some("firefox-private")
+            ),
+        ]
+    );
+}
+
+#[test]
+fn groups_in_any_order() {
+    let tool = item("action-first.desktop");
+    assert_eq!(&*tool.name, "Tool");
+    // listed actions without a group are dropped
+    assert_eq!(tool.actions.len(), 1);
+    assert_eq!(tool.actions[0].action.as_deref(), Some("edit"));
+    assert_eq!(tool.actions[0].exec.as_deref(), Some("tool --edit"));
+}
+
+#[test]
+fn wine_crlf_bom_escapes() {
+    let wine = item("wine.desktop");
+    assert_eq!(&*wine.name, "Wine Game");
+    assert_eq!(wine.comment.as_deref(), Some("First line\nsecond line"));
+    assert_eq!(wine.keywords, ["game;s", "wine"]);
+    // Exec keeps its escapes for the launcher's own quoting rules
+    assert_eq!(
+        wine.exec.as_deref(),
+        Some(r#"env WINEPREFIX="/home/me/My Games" wine "C:\\\\Games\\\\game.exe""#)
+    );
+    assert_eq!(wine.path.as_deref(), Some(Path::new("/home/me/My Games")));
+    assert_eq!(wine.icon.as_deref(), Some("4A2B_game.0"));
+    let raw = std::fs::read_to_string(fixture("wine.desktop")).unwrap();
+    assert!(
+        raw.starts_with('\u{feff}') && raw.contains("\r\n"),
+        "fixture lost its BOM or CRLF"
+    );
+    assert_eq!(parse_entry(&raw).raw(MAIN_GROUP, "Type"), Some("Application"));
+}
+
+#[test]
+fn show_conditions() {
+    let panel = item("gnome-only.desktop");
+    assert_eq!(panel.only_show_in, ["GNOME", "Unity"]);
+    assert_eq!(panel.not_show_in, ["KDE"]);
+    assert_eq!(panel.try_exec.as_deref(), Some("gnome-control-center"));
+    assert!(panel.no_display && panel.dbus_activatable && panel.prefers_dgpu);
+}
+
+#[test]
+fn links_and_errors() {
+    let link = item("link.desktop");
+    assert_eq!(link.url.as_deref(), Some("https://example.com/docs"));
+    assert!(link.exec.is_none());
+    assert!(item("hidden.desktop").deleted);
+    assert!(matches!(
+        Item::from_desktop(fixture("link-no-url.desktop")),
+        Err(EntryError::LinkWithoutUrl)
+    ));
+    assert!(matches!(
+        Item::from_desktop(fixture("no-name.desktop")),
+        Err(EntryError::NoName)
+    ));
+    assert!(matches!(
+        Item::from_desktop(fixture("missing.desktop")),
+        Err(EntryError::Unreadable(_))
+    ));
+    assert!(matches!(
+        Item::from_desktop(fixture("../README")),
+        Err(EntryError::NotDesktop)
+    ));
+}
+
+#[test]
+fn corpus() {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let names = |include_hidden| {
+        let mut names: Vec<String> = applications_in(vec![data_dir.clone()], include_hidden, &[], None, false)
+            .iter()
+            .map(|i| i.name.to_string())
+            .collect();
+        names.sort();
+        names
+    };
+    assert_eq!(names(false), ["Firefox", "Project Docs", "Tool", "Wine Game"]);
+    // deleted and malformed entries never show
+    assert_eq!(
+        names(true),
+        ["Firefox", "Project Docs", "Settings Panel", "Tool", "Wine Game"]
+    );
+}
@@ -0,0 +1,45 @@
+// SIGUSR1 reloads the theme, so it must be caught from the start rather than only once the window is up
+#![cfg(feature = "gui")]
+
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Whether `pid` has a handler for `signal`, from the caught mask in /proc
+fn catches(pid: u32, signal: i32) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/status", pid))
+        .ok()
+        .and_then(|status| {
+            let mask = status.lines().find_map(|l| l.strip_prefix("SigCgt:"))?;
+            u64::from_str_radix(mask.trim(), 16).ok()
+        })
+        .is_some_and(|mask| mask & 1 << (signal - 1) != 0)
+}
+
+#[test]
+fn reload_before_window() {
+    let mut linch = Command::new(env!("CARGO_BIN_EXE_linch"))
+        .arg("dmenu")
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // still reading stdin, so nothing has been loaded yet
+    let start = Instant::now();
+    while !catches(linch.id(), libc::SIGUSR1) {
+        assert!(start.elapsed() < Duration::from_secs(10), "SIGUSR1 was never caught");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    // SAFETY: kill has no memory preconditions, and the child hasn't been waited on so the pid is its own
+    assert_eq!(unsafe { libc::kill(linch.id() as i32, libc::SIGUSR1) }, 0);
+    let mut stdin = linch.stdin.take().unwrap();
+    stdin.write_all(b"one\ntwo\n").unwrap();
+    drop(stdin);
+    // without a display the window can't open, but that's the only way it should end
+    let status = linch.wait().unwrap();
+    assert_eq!(status.signal(), None, "{}", status);
+}